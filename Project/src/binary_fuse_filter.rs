@@ -1,133 +1,481 @@
-use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
-use rand::Rng;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
 use std::time::{Duration, Instant};
-use std::collections::VecDeque;
+use crate::error::{ConstructionError, SerializationError};
+use crate::filter::Filter;
+use crate::fingerprint::Fingerprint;
+use crate::hasher::DefaultBuildHasher;
+use crate::reduce::fastrange;
+use crate::seed::{random_seed, SplitMix64};
+use crate::serialization::{self, bytes_to_fingerprints, fingerprints_to_bytes, to_usize, Encoded, FilterKind, SerializableHasher};
 
-struct BFFilter {
-    b: Vec<u8>,
-    h0_seed: u64,
-    h1_seed: u64,
-    h2_seed: u64,
+const MAX_ATTEMPTS: usize = 100;// seeds tried before construction gives up
+
+/// F is the fingerprint type: u8, u16 or u32, giving a false positive rate of 1/2^8, 1/2^16 or 1/2^32.
+/// ARITY is the number of slots per key, 3 or 4. The 4-wise layout needs about 1.075 slots per key
+/// instead of 1.125, at the cost of one more memory access per lookup and slower construction.
+/// S builds the hasher used on keys.
+pub struct BFFilter<F: Fingerprint = u8, const ARITY: usize = 3, S = DefaultBuildHasher> {
+    b: Vec<F>,
+    seeds: [u64; ARITY],
     c: usize,
     block_size: usize,
+    num_keys: usize,
+    seed: u64,// every attempt's hash seeds are drawn from it
+    hash_builder: S,
 }
 
-impl BFFilter {
-    fn fingerprint<T: Hash+Clone+Eq>(&self, x: &T) -> u8 {
-        let mut s = DefaultHasher::new();
-        x.hash(&mut s);
-        let hash_value = s.finish();
-        let mask = (1 << 7) - 1; // For 7-bit fingerprints aiming for 0.0078 fpr. However, theoretical bit/item is the same as 8 bit fingerprint due to datatype.
-        ((hash_value >> (64 - 7)) & mask) as u8
+/// 3-wise binary fuse filter with 8-bit fingerprints, false positive rate about 0.39%.
+pub type BinaryFuse8 = BFFilter<u8>;
+/// 3-wise binary fuse filter with 16-bit fingerprints.
+pub type BinaryFuse16 = BFFilter<u16>;
+/// 3-wise binary fuse filter with 32-bit fingerprints.
+pub type BinaryFuse32 = BFFilter<u32>;
+/// 4-wise binary fuse filter with 8-bit fingerprints: smaller table, slower construction.
+pub type BinaryFuse4Wise8 = BFFilter<u8, 4>;
+/// 4-wise binary fuse filter with 16-bit fingerprints.
+pub type BinaryFuse4Wise16 = BFFilter<u16, 4>;
+/// 4-wise binary fuse filter with 32-bit fingerprints.
+pub type BinaryFuse4Wise32 = BFFilter<u32, 4>;
+
+// Construction and lookups only ever look at this 64-bit hash of a key.
+fn key_hash<T: Hash + ?Sized, S: BuildHasher>(hash_builder: &S, key: &T) -> u64 {
+    hash_builder.hash_one(key)
+}
+
+// (block_size, c) for n keys, following the binary fuse filter paper: the segment length is a power
+// of 2 growing slowly with n, and the array holds n * size_factor slots rounded up to whole segments.
+// c is a multiple of block_size, and the first slot of a key can fall in any but the last arity - 1
+// segments.
+fn layout(n: usize, arity: usize) -> (usize, usize) {
+    let n_f = n.max(2) as f64;
+    let (block_size, size_factor) = if arity == 4 {
+        (2f64.powf((n_f.ln() / 2.91f64.ln() - 0.5).floor()), f64::max(1.075, 0.77 + 0.305 * 600000f64.ln() / n_f.ln()))
+    } else {
+        (2f64.powf((n_f.ln() / 3.33f64.ln() + 2.25).floor()), f64::max(1.125, 0.875 + 0.25 * 1000000f64.ln() / n_f.ln()))
+    };
+    let block_size = block_size.clamp(4f64, 262144f64) as usize;
+    // a single key only needs its own arity slots
+    let capacity = if n <= 1 { 0 } else { (n as f64 * size_factor).round() as usize };
+    let num_segments = capacity.div_ceil(block_size).saturating_sub(arity - 1).max(1);
+    (block_size, (num_segments + arity - 1) * block_size)
+}
+
+// First slot of a key, anywhere but the last ARITY - 1 segments.
+fn first_slot(seed: u64, arity: usize, c: usize, block_size: usize, hash: u64) -> usize {
+    fastrange(seed.wrapping_mul(hash), c-(arity-1)*block_size)
+}
+
+fn slots<const ARITY: usize>(seeds: &[u64; ARITY], c: usize, block_size: usize, hash: u64) -> [usize; ARITY] {
+    let h0=first_slot(seeds[0], ARITY, c, block_size, hash);
+    std::array::from_fn(|i| {
+        if i == 0 {
+            h0
+        } else {
+            fastrange(seeds[i].wrapping_mul(hash), block_size) + (h0/block_size+i)*block_size
+        }
+    })
+}// ARITY wise binary fuse filter: the other slots fall in the segments following the one of h0.
+
+impl<F: Fingerprint, const ARITY: usize> BFFilter<F, ARITY> {
+    /// Builds a filter from keys; repeated keys are stored once.
+    pub fn new<T: Hash>(original_keys: & [T]) -> Result<Self, ConstructionError> {
+        Self::with_hasher(original_keys, random_seed(), DefaultBuildHasher::default())
     }
 
-    fn hash<T: Hash+Clone+Eq>(&self, item: &T) -> (usize,usize,usize) {
-        let mut hasher0 = DefaultHasher::new();
-        item.hash(&mut hasher0);
-        let hash0 = hasher0.finish();
-        let h0=(((self.h0_seed.wrapping_mul(hash0)) >> 32) % (self.c-2*self.block_size) as u64) as usize;
+    /// Same keys and seed always give the same filter.
+    pub fn with_seed<T: Hash>(original_keys: &[T], seed: u64) -> Result<Self, ConstructionError> {
+        Self::with_hasher(original_keys, seed, DefaultBuildHasher::default())
+    }
 
-        let mut hasher1 = DefaultHasher::new();
-        item.hash(&mut hasher1);
-        let hash1 = hasher1.finish();
-        let h1=(((self.h1_seed.wrapping_mul(hash1)) >> 32) % self.block_size as u64) as usize + (h0/self.block_size+1)*self.block_size;
+    /// Builds a filter from key hashes computed with the default hasher, e.g. by
+    /// DefaultBuildHasher::default().hash_one(key).
+    pub fn from_hashes(key_hashes: &[u64]) -> Result<Self, ConstructionError> {
+        Self::from_hashes_and_seed(key_hashes, random_seed())
+    }
 
-        let mut hasher2 = DefaultHasher::new();
-        item.hash(&mut hasher2);
-        let hash2 = hasher2.finish();
-        let h2 =(((self.h2_seed.wrapping_mul(hash2)) >> 32) % self.block_size as u64) as usize + (h0/self.block_size+2)*self.block_size;
-        (h0,h1,h2)
-    }//reorganized to reduce hash access times. 3 wise binary fuse filter.
+    /// Same as from_hashes with a fixed seed.
+    pub fn from_hashes_and_seed(key_hashes: &[u64], seed: u64) -> Result<Self, ConstructionError> {
+        Self::from_hashes_with_hasher(key_hashes, seed, DefaultBuildHasher::default())
+    }
+}
 
+impl<F: Fingerprint, const ARITY: usize, S: BuildHasher> BFFilter<F, ARITY, S> {
+    fn fingerprint(&self, hash: u64) -> F {
+        F::from_hash(hash)
+    }
 
-    fn map<T: Hash+Clone+Eq>(&self, buffer: Vec<(T,(usize,usize,usize))>) -> (bool, Vec<(T, usize)>) {
-        let mut h: Vec<Vec<T>> = vec![Vec::new(); self.c];
-        let key_num = buffer.len();
-        for x in buffer {
-            let (index0,index1,index2)=x.1;
-            h[index0].push(x.0.clone());
-            h[index1].push(x.0.clone());
-            h[index2].push(x.0.clone());
-        }
+    fn hash(&self, hash: u64) -> [usize; ARITY] {
+        slots(&self.seeds, self.c, self.block_size, hash)
+    }
+
+    // Segment of the first slot of a key, without computing its other slots.
+    fn segment_of(&self, hash: u64) -> usize {
+        first_slot(self.seeds[0], ARITY, self.c, self.block_size, hash) / self.block_size
+    }
 
-        let mut queue: VecDeque<usize> = VecDeque::new();
+    // Counting sort of the hashes by the segment of their first slot, so that filling the count
+    // and xor arrays below walks through memory roughly in order.
+    fn sort_by_segment(&self, hashes: &[u64]) -> Vec<u64> {
+        let num_segments = self.c / self.block_size;
+        let mut offsets: Vec<usize> = vec![0; num_segments + 1];
+        for &hash in hashes {
+            offsets[self.segment_of(hash) + 1] += 1;
+        }
+        for i in 1..=num_segments {
+            offsets[i] += offsets[i - 1];
+        }
+        let mut sorted: Vec<u64> = vec![0; hashes.len()];
+        for &hash in hashes {
+            let segment = self.segment_of(hash);
+            sorted[offsets[segment]] = hash;
+            offsets[segment] += 1;
+        }
+        sorted
+    }
 
-        for i in 0..h.len() {
-            if h[i].len() == 1 {
-                queue.push_back(i);
+    // Peeling on hashes only: every slot keeps how many keys map to it and the xor of their
+    // hashes, so a slot with count 1 directly yields the hash of its only key.
+    fn map(&self, hashes: &[u64]) -> (bool, Vec<(u64, usize)>) {
+        let mut count: Vec<u32> = vec![0; self.c];
+        let mut xor_hash: Vec<u64> = vec![0; self.c];
+        for &hash in hashes {
+            for idx in self.hash(hash) {
+                count[idx] += 1;
+                xor_hash[idx] ^= hash;
             }
         }
-        let mut stack: Vec<(T, usize)> = Vec::new();
-        while let Some(i) = queue.pop_front() {
-            if h[i].len() == 1 {
-                if let Some(x) = h[i].get(0).cloned(){
-                    stack.push((x.clone(), i));
-                    let (index0,index1,index2)=self.hash(&x);
-                    for &idx in &[index0, index1, index2] {
-                        if let Some(pos) = h[idx].iter().position(|item| *item == x) {
-                            h[idx].remove(pos);
-                        }
-                        if h[idx].len() == 1 {
-                            queue.push_back(idx);
-                        }
+
+        let mut queue: Vec<usize> = (0..self.c).filter(|&i| count[i] == 1).collect();
+        let mut stack: Vec<(u64, usize)> = Vec::with_capacity(hashes.len());
+        while let Some(i) = queue.pop() {
+            if count[i] == 1 {
+                let hash = xor_hash[i];
+                stack.push((hash, i));
+                for idx in self.hash(hash) {
+                    count[idx] -= 1;
+                    xor_hash[idx] ^= hash;
+                    if count[idx] == 1 {
+                        queue.push(idx);
                     }
                 }
             }
         }
-        let mut new_stack = stack.clone();
-        new_stack.reverse();//make sure the stack behave like FILO in assign.
-        (stack.len() == key_num, new_stack)
+        (stack.len() == hashes.len(), stack)
     }
 
-    fn assign<T:Hash+Clone+Eq>(&mut self, stack: Vec<(T, usize)>) {
-        for (x, i) in stack {
-            let (index0,index1,index2)=self.hash(&x);
-            let fp = self.fingerprint(&x);
-            self.b[i] = 0;
-            self.b[i] = fp ^ self.b[index0] ^ self.b[index1] ^ self.b[index2];
+    fn assign(&mut self, stack: Vec<(u64, usize)>) {
+        for (hash, i) in stack.into_iter().rev() {//the last key peeled is assigned first.
+            let fp = self.fingerprint(hash);
+            self.b[i] = F::default();
+            self.b[i] = self.hash(hash).iter().fold(fp, |acc, &idx| acc ^ self.b[idx]);
         }
     }
 
-    fn contains<T:Hash+Clone+Eq>(&self, key: &T) -> bool {
-        let fp = self.fingerprint(key); // Calculate fingerprint of the key
-        let (h0_index,h1_index,h2_index) = self.hash(key);
-        // XOR the values stored at these indices in array `B`
-        let computed_fp = self.b[h0_index] ^ self.b[h1_index] ^ self.b[h2_index];
+    /// Returns true if the key may have been in the construction set, false if it definitely was not.
+    pub fn contains<T: Hash + ?Sized>(&self, key: &T) -> bool {
+        self.contains_hash(key_hash(&self.hash_builder, key))
+    }
+
+    /// Same as contains for a caller that already hashed the key with this filter's hasher.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        let fp = self.fingerprint(hash); // Calculate fingerprint of the key
+        // XOR the values stored at the key's slots in array `B`
+        let computed_fp = self.hash(hash).iter().fold(F::default(), |acc, &idx| acc ^ self.b[idx]);
         // Return true if the computed fingerprint matches the key's fingerprint
         computed_fp == fp
     }
 
-    fn new<T: Hash+Clone+Eq>(original_keys: & [T]) -> Self {
-        let mut rng = rand::thread_rng();
-        let n = original_keys.len();
-        let block_size = (4.8*(n as f64).powf(0.58)) as usize;
-        let c = (((1.125*n as f64).floor()/block_size as f64).ceil() * block_size as f64) as usize; //make sure it can be divided by block size.
-        loop {
-            let mut filter = BFFilter {
-                b: vec![0; c],
-                h0_seed:rng.gen::<u64>() | 1,
-                h1_seed:rng.gen::<u64>() | 1,
-                h2_seed:rng.gen::<u64>() | 1,
-                c,
-                block_size,
-            };
-            let mut buffer: Vec<(&T,(usize,usize,usize))> = Vec::new();
-            for key in original_keys{
-                buffer.push((key,filter.hash(key)));
-            }
-            buffer.sort_by_cached_key(|key_hash_pair| key_hash_pair.1.0/filter.block_size);
-            //store the hash value in a buffer to reduce needed hash access. sorting algorithm is not the best.
-            let (success, stack) = filter.map(buffer);
+    /// Builds a filter from keys hashed with hash_builder; repeated keys are stored once.
+    pub fn with_hasher<T: Hash>(original_keys: &[T], seed: u64, hash_builder: S) -> Result<Self, ConstructionError> {
+        let hashes: Vec<u64> = original_keys.iter().map(|key| key_hash(&hash_builder, key)).collect();
+        Self::from_hashes_with_hasher(&hashes, seed, hash_builder)
+    }
+
+    /// Builds a filter from key hashes already computed with hash_builder.
+    pub fn from_hashes_with_hasher(key_hashes: &[u64], seed: u64, hash_builder: S) -> Result<Self, ConstructionError> {
+        assert!(ARITY == 3 || ARITY == 4, "binary fuse filters are 3-wise or 4-wise");
+        let hashes = unique_hashes(key_hashes);
+        let (block_size, c) = layout(hashes.len(), ARITY);
+        Self::build(&hashes, block_size, c, seed, hash_builder)
+    }
+
+    // Retries seeds until the distinct hashes peel in c slots split into segments of block_size.
+    fn build(hashes: &[u64], block_size: usize, c: usize, seed: u64, hash_builder: S) -> Result<Self, ConstructionError> {
+        let mut rng = SplitMix64::new(seed);
+        let n = hashes.len();
+        let mut filter = BFFilter {
+            b: vec![F::default(); c],
+            seeds: [0; ARITY],
+            c,
+            block_size,
+            num_keys: n,
+            seed,
+            hash_builder,
+        };
+        for _ in 0..MAX_ATTEMPTS {
+            filter.seeds = std::array::from_fn(|_| rng.next_odd());
+            let sorted = filter.sort_by_segment(hashes);
+            let (success, stack) = filter.map(&sorted);
             if success {
                 filter.assign(stack);
-                return filter;
-            }//Average retry times more than xor, so more "worse" case can happen. Average construction time increases.
+                return Ok(filter);
+            }//With the published sizing the first seed almost always succeeds.
             // If not successful, loop will continue and try with new seeds
         }
+        Err(ConstructionError::TooManyRetries { attempts: MAX_ATTEMPTS })
+    }
+
+    /// Number of distinct keys the filter was built from.
+    pub fn len(&self) -> usize {
+        self.num_keys
+    }
+
+    /// True if the filter was built from no keys.
+    pub fn is_empty(&self) -> bool {
+        self.num_keys == 0
+    }
+
+    /// Seed the filter was built with; passing it back to a constructor rebuilds the same filter.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Bytes of the fingerprint table.
+    pub fn size_in_bytes(&self) -> usize {
+        self.b.len() * std::mem::size_of::<F>()
+    }
+
+    /// One in 2^BITS negative keys matches the fingerprint by chance.
+    pub fn expected_fpr(&self) -> f64 {
+        1f64 / 2f64.powi(F::BITS as i32)
+    }
+}
+
+impl<T: Hash + ?Sized, F: Fingerprint, const ARITY: usize, S: BuildHasher> Filter<T> for BFFilter<F, ARITY, S> {
+    fn contains(&self, item: &T) -> bool {
+        BFFilter::<F, ARITY, S>::contains(self, item)
+    }
+
+    fn len(&self) -> usize {
+        BFFilter::<F, ARITY, S>::len(self)
+    }
+
+    // A static filter holds exactly the keys it was built from.
+    fn capacity(&self) -> usize {
+        BFFilter::<F, ARITY, S>::len(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        BFFilter::<F, ARITY, S>::size_in_bytes(self)
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        BFFilter::<F, ARITY, S>::expected_fpr(self)
+    }
+}
+
+struct Params<const ARITY: usize> {
+    num_keys: usize,
+    c: usize,
+    block_size: usize,
+    seeds: [u64; ARITY],
+}
+
+impl<const ARITY: usize> Params<ARITY> {
+    fn decode<F: Fingerprint>(params: &[u64]) -> Result<Self, SerializationError> {
+        if params.len() != 5 + ARITY {
+            return Err(SerializationError::InvalidParameters("parameter count differs from the filter type"));
+        }
+        if params[0] != F::BITS as u64 || params[1] != ARITY as u64 {
+            return Err(SerializationError::InvalidParameters("fingerprint width or arity differs from the filter type"));
+        }
+        let c = to_usize(params[3])?;
+        let block_size = to_usize(params[4])?;
+        if block_size == 0 || c % block_size != 0 || c / block_size < ARITY {
+            return Err(SerializationError::InvalidParameters("binary fuse filter layout out of range"));
+        }
+        Ok(Params { num_keys: to_usize(params[2])?, c, block_size, seeds: std::array::from_fn(|i| params[5 + i]) })
+    }
+}
+
+// Names of the parameter words, in the order to_bytes writes them. serde_support uses them as field
+// names. A filter writes one seed per hash, so only the first 5 + ARITY are used.
+pub(crate) const PARAM_NAMES: [&str; 9] = ["fingerprint_bits", "arity", "num_keys", "num_slots", "block_size", "seed0", "seed1", "seed2", "seed3"];
+
+// num_slots is c and the payload is the fingerprint array. The hash seeds are stored since the
+// attempt that found them is not.
+impl<F: Fingerprint, const ARITY: usize, S: SerializableHasher> BFFilter<F, ARITY, S> {
+    /// Serializes the filter in the format described in serialization.rs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut params = vec![F::BITS as u64, ARITY as u64, self.num_keys as u64, self.c as u64, self.block_size as u64];
+        params.extend_from_slice(&self.seeds);
+        debug_assert!(params.len() <= PARAM_NAMES.len(), "every parameter word needs a name");
+        serialization::encode(FilterKind::BinaryFuse, S::ID, self.hash_builder.state(), self.seed, &params, &fingerprints_to_bytes(&self.b))
+    }
+
+    /// Writes to_bytes() to writer.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        serialization::write_all(writer, &self.to_bytes())
+    }
+
+    /// Loads a filter written by to_bytes, checking its framing, checksum, fingerprint width and arity.
+    pub fn from_bytes_with_hasher(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let encoded = serialization::decode(bytes, FilterKind::BinaryFuse, S::ID)?;
+        let params = Params::<ARITY>::decode::<F>(&encoded.params)?;
+        Ok(BFFilter {
+            b: bytes_to_fingerprints(encoded.payload, params.c)?,
+            seeds: params.seeds,
+            c: params.c,
+            block_size: params.block_size,
+            num_keys: params.num_keys,
+            seed: encoded.seed,
+            hash_builder: S::from_state(encoded.hasher_state),
+        })
+    }
+
+    /// Reads all of reader and loads it with from_bytes_with_hasher.
+    pub fn read_from_with_hasher<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
     }
 }
 
+impl<F: Fingerprint, const ARITY: usize> BFFilter<F, ARITY> {
+    /// Loads a filter built with the default hasher, see from_bytes_with_hasher.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(bytes)
+    }
+
+    /// Reads all of reader and loads it with from_bytes.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
+    }
+}
+
+/// Read-only binary fuse filter answering queries straight from the bytes written by
+/// BFFilter::to_bytes, e.g. a memory-mapped file, without copying the fingerprints. The bytes must
+/// start at an address aligned for F. Opening checks the checksum, which reads the whole buffer once;
+/// from_bytes_unchecked skips it.
+pub struct BFFilterRef<'a, F: Fingerprint = u8, const ARITY: usize = 3, S = DefaultBuildHasher> {
+    b: &'a [F],
+    seeds: [u64; ARITY],
+    c: usize,
+    block_size: usize,
+    num_keys: usize,
+    seed: u64,
+    hash_builder: S,
+}
+
+impl<'a, F: Fingerprint, const ARITY: usize> BFFilterRef<'a, F, ARITY> {
+    /// Opens a view over a filter built with the default hasher, see from_bytes_with_hasher.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(bytes)
+    }
+
+    /// Opens a view over a filter built with the default hasher, see from_bytes_unchecked_with_hasher.
+    pub fn from_bytes_unchecked(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_unchecked_with_hasher(bytes)
+    }
+}
+
+impl<'a, F: Fingerprint, const ARITY: usize, S: SerializableHasher> BFFilterRef<'a, F, ARITY, S> {
+    /// Opens a view over bytes written by BFFilter::to_bytes without copying the fingerprints.
+    pub fn from_bytes_with_hasher(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::open(serialization::decode(bytes, FilterKind::BinaryFuse, S::ID)?)
+    }
+
+    /// Same as from_bytes_with_hasher without verifying the checksum, so opening does not read the
+    /// whole buffer. The header, lengths and alignment are still checked and queries stay in bounds,
+    /// but a corrupted fingerprint table gives wrong answers instead of an error: only use it on bytes known
+    /// to be intact, e.g. opened once with from_bytes after they were written.
+    pub fn from_bytes_unchecked_with_hasher(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::open(serialization::decode_unchecked(bytes, FilterKind::BinaryFuse, S::ID)?)
+    }
+
+    fn open(encoded: Encoded<'a>) -> Result<Self, SerializationError> {
+        let params = Params::<ARITY>::decode::<F>(&encoded.params)?;
+        if Some(encoded.payload.len()) != params.c.checked_mul(F::BITS as usize / 8) {
+            return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
+        }
+        Ok(BFFilterRef {
+            b: F::cast_slice(encoded.payload).ok_or(SerializationError::Misaligned)?,
+            seeds: params.seeds,
+            c: params.c,
+            block_size: params.block_size,
+            num_keys: params.num_keys,
+            seed: encoded.seed,
+            hash_builder: S::from_state(encoded.hasher_state),
+        })
+    }
+
+    /// Same as BFFilter::contains.
+    pub fn contains<T: Hash + ?Sized>(&self, key: &T) -> bool {
+        self.contains_hash(key_hash(&self.hash_builder, key))
+    }
+
+    /// Same as BFFilter::contains_hash.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        let computed_fp = slots(&self.seeds, self.c, self.block_size, hash).iter().fold(F::default(), |acc, &idx| acc ^ self.b[idx]);
+        computed_fp == F::from_hash(hash)
+    }
+
+    /// Number of distinct keys the filter was built from.
+    pub fn len(&self) -> usize {
+        self.num_keys
+    }
+
+    /// True if the filter was built from no keys.
+    pub fn is_empty(&self) -> bool {
+        self.num_keys == 0
+    }
+
+    /// Seed the filter was built with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Bytes of the borrowed fingerprint array.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.b)
+    }
+
+    /// 1 / 2^BITS of the fingerprint type, the chance that a missing key matches.
+    pub fn expected_fpr(&self) -> f64 {
+        1f64 / 2f64.powi(F::BITS as i32)
+    }
+}
+
+impl<T: Hash + ?Sized, F: Fingerprint, const ARITY: usize, S: SerializableHasher> Filter<T> for BFFilterRef<'_, F, ARITY, S> {
+    fn contains(&self, item: &T) -> bool {
+        BFFilterRef::<F, ARITY, S>::contains(self, item)
+    }
+
+    fn len(&self) -> usize {
+        BFFilterRef::<F, ARITY, S>::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        BFFilterRef::<F, ARITY, S>::len(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        BFFilterRef::<F, ARITY, S>::size_in_bytes(self)
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        BFFilterRef::<F, ARITY, S>::expected_fpr(self)
+    }
+}
+
+// Keys with the same 64-bit hash map to the same slots and fingerprint, so peeling can never
+// separate them. Each hash value is kept once.
+fn unique_hashes(key_hashes: &[u64]) -> Vec<u64> {
+    let mut hashes = key_hashes.to_vec();
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes
+}
+
 fn compute_mean_and_variance(times: &[Duration]) -> (f64, f64) {
     let times_in_secs: Vec<f64> = times.iter()
         .map(|d| d.as_secs_f64())
@@ -142,6 +490,7 @@ fn compute_mean_and_variance(times: &[Duration]) -> (f64, f64) {
     (mean, variance)
 }
 
+/// Benchmarks the binary fuse filters and prints space, false positive rate and timings.
 pub fn test_bff(){
     //run a single test
     let mut pos_keys: Vec<u32> = (1..=996147).collect();
@@ -149,9 +498,9 @@ pub fn test_bff(){
     let pos_key_len = pos_keys.len();
     let neg_key_len = neg_keys.len();
     let xor_construction_start=Instant::now();
-    let filter = BFFilter::new(pos_keys.as_mut_slice());
+    let filter = BinaryFuse8::new(pos_keys.as_mut_slice()).expect("binary fuse filter construction failed");
     let xor_construction_duration = xor_construction_start.elapsed();
-    let bits_per_item =(filter.c*8) as f64/pos_key_len as f64;
+    let bits_per_item =(filter.size_in_bytes()*8) as f64/pos_key_len as f64;
     println!("BF bits/item is {:.2}", bits_per_item);
     println!("BF Filter Construction Time per item for {:?} items: {:?}", pos_key_len,xor_construction_duration/pos_key_len as u32);
     let mut true_positives = 0;
@@ -177,6 +526,21 @@ pub fn test_bff(){
     println!("BF Filter lookup time per item for {:?} non-inserted items: {:?}", neg_key_len,neg_key_check_duration/neg_key_len as u32);
     println!("BF False Positive Rate (FPR): {:.6}", fpr);
 
+    //run a single test of the 4-wise variant
+    let pos_keys: Vec<u32> = (1..=996147).collect();
+    let neg_keys: Vec<u32> = (996148..=1992296).collect();
+    let bff4_construction_start=Instant::now();
+    let filter = BinaryFuse4Wise8::new(&pos_keys).expect("4-wise binary fuse filter construction failed");
+    let bff4_construction_duration = bff4_construction_start.elapsed();
+    let bits_per_item =(filter.size_in_bytes()*8) as f64/pos_key_len as f64;
+    println!("4-wise BF bits/item is {:.2}", bits_per_item);
+    println!("4-wise BF Filter Construction Time per item for {:?} items: {:?}", pos_key_len,bff4_construction_duration/pos_key_len as u32);
+    let neg_key_check_start = Instant::now();
+    let false_positives = neg_keys.iter().filter(|key| filter.contains(*key)).count();
+    let neg_key_check_duration = neg_key_check_start.elapsed();
+    println!("4-wise BF Filter lookup time per item for {:?} non-inserted items: {:?}", neg_keys.len(),neg_key_check_duration/neg_keys.len() as u32);
+    println!("4-wise BF False Positive Rate (FPR): {:.6}", false_positives as f64 / neg_keys.len() as f64);
+
     //run multiple tests
     let test_num = 20;
     let mut construct_times: Vec<Duration> = Vec::with_capacity(test_num);
@@ -189,7 +553,7 @@ pub fn test_bff(){
         let pos_keys: Vec<u32> = (1..=num_of_keys).collect();
         let neg_keys: Vec<u32> = (num_of_keys+1..=2*num_of_keys).collect();
         let bff_construction_start = Instant::now();
-        let filter = BFFilter::new(&pos_keys);
+        let filter = BinaryFuse8::new(&pos_keys).expect("binary fuse filter construction failed");
         let bff_construction_duration = bff_construction_start.elapsed();
         construct_times.push(bff_construction_duration);
 
@@ -216,4 +580,79 @@ pub fn test_bff(){
     println!("BFF: Positive Check for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", num_of_keys, pos_check_mean, pos_check_variance);
     println!("BFF: Negative Check for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", num_of_keys, neg_check_mean, neg_check_variance);
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::tests::{assert_rejects_corruption, assert_round_trips, at_offset};
+
+    fn keys() -> Vec<u32> {
+        (0..1000).collect()
+    }
+
+    #[test]
+    fn repeated_keys_are_stored_once() {
+        let filter = BinaryFuse8::with_seed(&[5u32; 1000], 1).unwrap();
+        assert_eq!(filter.len(), 1);
+        assert!(filter.contains(&5u32));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts_in_an_undersized_table() {
+        let hashes: Vec<u64> = (0..1000u64).map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15)).collect();
+        let result = {
+            let (block_size, c) = layout(10, 3);
+            BinaryFuse8::build(&hashes, block_size, c, 7, DefaultBuildHasher::default())
+        };
+        assert!(matches!(result, Err(ConstructionError::TooManyRetries { attempts: MAX_ATTEMPTS })));
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let filter = BinaryFuse8::with_seed(&keys(), 7).unwrap();
+        let loaded = assert_round_trips(&filter.to_bytes(), BinaryFuse8::from_bytes, |bytes| BinaryFuse8::read_from(bytes), |filter, out| filter.write_to(out));
+        assert_eq!(loaded.len(), filter.len());
+        assert!((0..2000u32).all(|i| loaded.contains(&i) == filter.contains(&i)));
+        let filter = BinaryFuse4Wise16::with_seed(&keys(), 7).unwrap();
+        assert_round_trips(&filter.to_bytes(), BinaryFuse4Wise16::from_bytes, |bytes| BinaryFuse4Wise16::read_from(bytes), |filter, out| filter.write_to(out));
+    }
+
+    #[test]
+    fn rejects_another_fingerprint_width_or_arity() {
+        let bytes = BinaryFuse8::with_seed(&keys(), 7).unwrap().to_bytes();
+        assert!(matches!(BinaryFuse16::from_bytes(&bytes), Err(SerializationError::InvalidParameters(_))));
+        assert!(matches!(BinaryFuse4Wise8::from_bytes(&bytes), Err(SerializationError::InvalidParameters(_))));
+    }
+
+    #[test]
+    fn view_answers_like_the_filter() {
+        let filter = BinaryFuse16::with_seed(&keys(), 7).unwrap();
+        at_offset(&filter.to_bytes(), 0, |bytes| {
+            let view = BFFilterRef::<u16>::from_bytes(bytes).unwrap();
+            assert_eq!(view.len(), filter.len());
+            assert!((0..2000u32).all(|i| view.contains(&i) == filter.contains(&i)));
+        });
+    }
+
+    #[test]
+    fn view_rejects_misaligned_or_corrupted_bytes() {
+        let bytes = BinaryFuse16::with_seed(&keys(), 7).unwrap().to_bytes();
+        at_offset(&bytes, 1, |bytes| assert!(matches!(BFFilterRef::<u16>::from_bytes(bytes), Err(SerializationError::Misaligned))));
+        at_offset(&bytes, 0, |bytes| assert_rejects_corruption(bytes, |bytes| BFFilterRef::<u16>::from_bytes(bytes).map(|_| ())));
+    }
+
+    #[test]
+    fn unchecked_view_skips_only_the_checksum() {
+        let filter = BinaryFuse16::with_seed(&keys(), 7).unwrap();
+        let mut bytes = filter.to_bytes();
+        let checksum = bytes.len() - 4;
+        bytes[checksum] ^= 1;
+        at_offset(&bytes, 0, |bytes| {
+            assert!(matches!(BFFilterRef::<u16>::from_bytes(bytes), Err(SerializationError::ChecksumMismatch { .. })));
+            let view = BFFilterRef::<u16>::from_bytes_unchecked(bytes).unwrap();
+            assert!((0..2000u32).all(|i| view.contains(&i) == filter.contains(&i)));
+        });
+        at_offset(&bytes, 1, |bytes| assert!(matches!(BFFilterRef::<u16>::from_bytes_unchecked(bytes), Err(SerializationError::Misaligned))));
+    }
+}
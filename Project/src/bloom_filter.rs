@@ -1,61 +1,494 @@
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::hash::{BuildHasher, Hash};
 use std::f64;
-use rand;
+use std::convert::Infallible;
+use std::io::{self, Read, Write};
 use std::time::{Duration, Instant};
+use crate::error::SerializationError;
+use crate::filter::{Filter, MutableFilter};
+use crate::hasher::DefaultBuildHasher;
+use crate::reduce::fastrange;
+use crate::seed::{random_seed, SplitMix64};
+use crate::serialization::{self, bytes_to_words, cast_words, to_usize, words_to_bytes, Encoded, FilterKind, SerializableHasher};
 
-// Define the BloomFilter struct
-pub struct BloomFilter {
-    bit_vec: Vec<bool>,
-    seeds: Vec<u64>,
+/// False positive rate used by the constructors that are not given one.
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.0074;
+
+/// m = -n ln(p) / (ln 2)^2, the number of bits needed for n items at false positive rate p.
+pub fn optimal_num_bits(expected_items: usize, false_positive_rate: f64) -> usize {
+    assert!(false_positive_rate > 0f64 && false_positive_rate < 1f64, "false positive rate must be in (0, 1)");
+    let size = (-(expected_items.max(1) as f64) * false_positive_rate.ln() / f64::ln(2f64).powi(2)).ceil() as usize;
+    size.max(1)
+}
+
+/// k = (m / n) ln 2, the number of hash functions minimising the false positive rate.
+pub fn optimal_num_hashes(num_bits: usize, expected_items: usize) -> usize {
+    let num_hashes = ((num_bits as f64 / expected_items.max(1) as f64) * f64::ln(2f64)).ceil() as usize;
+    num_hashes.max(1)
+}
+
+/// How the k bit positions of an item are derived from its 64-bit hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexingMode {
+    /// One multiply-shift per odd seed.
+    Seeded,
+    /// Kirsch-Mitzenmacher: position i is h1 + i * h2 reduced to m bits, h1 being the hash and h2 the
+    /// hash with its two 32-bit halves swapped. No seeds, and the same false positive rate as k
+    /// independent hashes.
+    DoubleHashing,
+}
+
+// Seeds of the k hash functions, all derived from the filter seed.
+pub(crate) fn derive_seeds(mode: IndexingMode, seed: u64, num_hashes: usize) -> Vec<u64> {
+    let mut rng = SplitMix64::new(seed);
+    match mode {
+        IndexingMode::Seeded => (0..num_hashes).map(|_| rng.next_odd()).collect(), // Ensure seeds are odd
+        IndexingMode::DoubleHashing => Vec::new(),
+    }
+}
+
+// Bit picked by the i-th hash function. Every one works on the same 64-bit hash of the item,
+// so the item itself is hashed once per add or contains.
+pub(crate) fn bit_index(mode: IndexingMode, seeds: &[u64], size: usize, hash: u64, i: usize) -> usize {
+    match mode {
+        IndexingMode::Seeded => fastrange(seeds[i].wrapping_mul(hash), size),
+        //multiply-shift. better distribution to avoid collision.
+        // fastrange works for any size, so for 1000000 items we don't need 1048576 bits when we have 7 hash functions to achieve 0.007 fpr.
+        IndexingMode::DoubleHashing => {
+            let (h1, h2) = (hash, hash.rotate_left(32));
+            fastrange(h1.wrapping_add((i as u64).wrapping_mul(h2)), size)
+        }
+    }
+}
+
+fn bit_is_set(bit_vec: &[u64], index: usize) -> bool {
+    bit_vec[index >> 6] & (1u64 << (index & 63)) != 0
+}
+
+// (1 - e^(-kn/m))^k with n being the number of items added so far.
+pub(crate) fn fpr_estimate(num_hashes: usize, num_items: usize, num_bits: usize) -> f64 {
+    let k = num_hashes as f64;
+    (1f64 - (-k * num_items as f64 / num_bits as f64).exp()).powf(k)
+}
+
+/// Bloom filter setting k bits per item in a vector of m bits. S builds the hasher used on items.
+pub struct BloomFilter<S = DefaultBuildHasher> {
+    bit_vec: Vec<u64>,// bit i lives in word i / 64 at position i % 64
+    seeds: Vec<u64>,// empty with IndexingMode::DoubleHashing
+    seed: u64,// the seeds above are all derived from it
+    num_hashes: usize,
+    mode: IndexingMode,
     size: usize,
+    expected_items: usize,
+    num_items: usize,
+    hash_builder: S,
 }
 
 impl BloomFilter {
-    // Initialize a new BloomFilter with a target false positive rate
-    fn new(expected_item_size: usize) -> BloomFilter {
+    /// Initialize a new BloomFilter with the default false positive rate of 0.0074
+    pub fn new(expected_item_size: usize) -> BloomFilter {
+        Self::with_fpr(expected_item_size, DEFAULT_FALSE_POSITIVE_RATE)
+    }
+
+    /// Initialize a new BloomFilter with a target false positive rate
+    pub fn with_fpr(expected_item_size: usize, false_positive_rate: f64) -> BloomFilter {
         // Calculate the size of the bit vector (m) and the number of hash functions (k)
-        let false_positive_rate: f64=0.0074;
-        let size = (-1f64 * (expected_item_size as f64) * false_positive_rate.ln() / f64::ln(2f64).powi(2)).ceil() as usize;// This is 'm', the size of the bit array
-        let num_hashes = ((size as f64 / expected_item_size as f64) * f64::ln(2f64)).ceil() as usize;// This is 'k', the number of hash functions
-        let seeds = (0..num_hashes).map(|_| rand::random::<u64>() | 1).collect(); // Ensure seeds are odd
+        let size = optimal_num_bits(expected_item_size, false_positive_rate);// This is 'm', the size of the bit array
+        Self::with_num_bits(expected_item_size, size)
+    }
+
+    /// Initialize a new BloomFilter with a bit budget, choosing the best 'k' for it
+    pub fn with_num_bits(expected_item_size: usize, num_bits: usize) -> BloomFilter {
+        Self::with_num_bits_and_seed(expected_item_size, num_bits, random_seed())
+    }
+
+    /// Same as with_num_bits with a fixed seed.
+    pub fn with_num_bits_and_seed(expected_item_size: usize, num_bits: usize, seed: u64) -> BloomFilter {
+        let num_hashes = optimal_num_hashes(num_bits, expected_item_size);// This is 'k', the number of hash functions
+        Self::with_params_and_seed(expected_item_size, num_bits, num_hashes, seed)
+    }
+
+    /// Initialize a new BloomFilter with explicit 'm' and 'k'
+    pub fn with_params(expected_item_size: usize, num_bits: usize, num_hashes: usize) -> BloomFilter {
+        Self::with_params_and_seed(expected_item_size, num_bits, num_hashes, random_seed())
+    }
+
+    /// Same as with_params with a fixed seed.
+    pub fn with_params_and_seed(expected_item_size: usize, num_bits: usize, num_hashes: usize, seed: u64) -> BloomFilter {
+        Self::with_params_and_hasher(expected_item_size, num_bits, num_hashes, seed, DefaultBuildHasher::default())
+    }
+
+    /// Initialize a new BloomFilter with a target false positive rate and a fixed seed. The same seed
+    /// and items always give the same bits.
+    pub fn with_seed(expected_item_size: usize, false_positive_rate: f64, seed: u64) -> BloomFilter {
+        Self::with_fpr_and_hasher(expected_item_size, false_positive_rate, seed, DefaultBuildHasher::default())
+    }
+
+    /// Initialize a new BloomFilter with a target false positive rate, using double hashing instead of seeds
+    pub fn with_double_hashing(expected_item_size: usize, false_positive_rate: f64) -> BloomFilter {
+        Self::with_double_hashing_and_seed(expected_item_size, false_positive_rate, random_seed())
+    }
+
+    /// Same as with_double_hashing with a fixed seed.
+    pub fn with_double_hashing_and_seed(expected_item_size: usize, false_positive_rate: f64, seed: u64) -> BloomFilter {
+        let size = optimal_num_bits(expected_item_size, false_positive_rate);
+        let num_hashes = optimal_num_hashes(size, expected_item_size);
+        Self::with_mode_and_hasher(expected_item_size, size, num_hashes, IndexingMode::DoubleHashing, seed, DefaultBuildHasher::default())
+    }
+}
+
+impl<S: BuildHasher> BloomFilter<S> {
+    /// Initialize a new BloomFilter with a target false positive rate, hashing items with hash_builder
+    pub fn with_fpr_and_hasher(expected_item_size: usize, false_positive_rate: f64, seed: u64, hash_builder: S) -> Self {
+        let size = optimal_num_bits(expected_item_size, false_positive_rate);
+        let num_hashes = optimal_num_hashes(size, expected_item_size);
+        Self::with_params_and_hasher(expected_item_size, size, num_hashes, seed, hash_builder)
+    }
+
+    /// Initialize a new BloomFilter with explicit 'm' and 'k', hashing items with hash_builder
+    pub fn with_params_and_hasher(expected_item_size: usize, num_bits: usize, num_hashes: usize, seed: u64, hash_builder: S) -> Self {
+        Self::with_mode_and_hasher(expected_item_size, num_bits, num_hashes, IndexingMode::Seeded, seed, hash_builder)
+    }
+
+    /// Initialize a new BloomFilter with explicit 'm', 'k' and indexing mode, hashing items with hash_builder
+    pub fn with_mode_and_hasher(expected_item_size: usize, num_bits: usize, num_hashes: usize, mode: IndexingMode, seed: u64, hash_builder: S) -> Self {
+        assert!(num_bits > 0, "a bloom filter needs at least one bit");
+        assert!(num_hashes > 0, "a bloom filter needs at least one hash function");
         BloomFilter {
-            bit_vec: vec![false; size],
-            seeds,
-            size,
+            bit_vec: vec![0u64; num_bits.div_ceil(64)],
+            seeds: derive_seeds(mode, seed, num_hashes),
+            seed,
+            num_hashes,
+            mode,
+            size: num_bits,
+            expected_items: expected_item_size,
+            num_items: 0,
+            hash_builder,
         }
     }
 
+    fn index(&self, hash: u64, i: usize) -> usize {
+        bit_index(self.mode, &self.seeds, self.size, hash, i)
+    }
 
-    fn hash<T: Hash>(&self, item: &T, seed: u64) -> usize {//allows a reference to type T.
-        let mut hasher = DefaultHasher::new();
-        item.hash(&mut hasher);
-        let hash = hasher.finish();//deal with generic type that implements Hash. so you don't have to map any type of element to an i32 number and then insert/look up things.
-        (((seed.wrapping_mul(hash)) >> 32) % self.size as u64) as usize
-        //multiply-shift. better distribution to avoid collision.
-        // size is not pow of two may lead to un-uniform, but it's the sacrifice to take so that for 1000000 items we don't need 1048576 bits when we have 7 hash functions to achieve 0.007 fpr.
-        // the real result is 0.0005 more.
+    /// Add an item to the Bloom filter
+    pub fn add<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.insert_hash(self.hash_builder.hash_one(item));//deal with generic type that implements Hash. so you don't have to map any type of element to an i32 number and then insert/look up things.
+    }
+
+    /// Check if an item might be in the Bloom filter
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.contains_hash(self.hash_builder.hash_one(item))
+    }
+
+    /// Same as add for a caller that already hashed the item with this filter's hasher.
+    pub fn insert_hash(&mut self, hash: u64) {
+        for i in 0..self.num_hashes {
+            let index = self.index(hash, i);
+            self.bit_vec[index >> 6] |= 1u64 << (index & 63);
+        }
+        self.num_items += 1;
+    }
+
+    /// Same as contains for a caller that already hashed the item with this filter's hasher.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        (0..self.num_hashes).all(|i| bit_is_set(&self.bit_vec, self.index(hash, i)))
+    }
+
+    /// Number of add calls so far (duplicates are counted again).
+    pub fn len(&self) -> usize {
+        self.num_items
+    }
+
+    /// True if nothing was added yet.
+    pub fn is_empty(&self) -> bool {
+        self.num_items == 0
+    }
+
+    /// Number of items the filter was sized for.
+    pub fn capacity(&self) -> usize {
+        self.expected_items
+    }
+
+    /// 'm', the number of bits in the filter.
+    pub fn num_bits(&self) -> usize {
+        self.size
+    }
+
+    /// 'k', the number of hash functions.
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    /// How bit positions are derived from an item's hash.
+    pub fn indexing_mode(&self) -> IndexingMode {
+        self.mode
+    }
+
+    /// Seed the filter was built with; passing it back to a constructor rebuilds the same filter.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Bytes of the bit vector.
+    pub fn size_in_bytes(&self) -> usize {
+        self.bit_vec.len() * std::mem::size_of::<u64>()
+    }
+
+    /// False positive rate expected with the items added so far.
+    pub fn expected_fpr(&self) -> f64 {
+        fpr_estimate(self.num_hashes, self.num_items, self.size)
+    }
+
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> Filter<T> for BloomFilter<S> {
+    fn contains(&self, item: &T) -> bool {
+        BloomFilter::<S>::contains(self, item)
+    }
+
+    fn len(&self) -> usize {
+        BloomFilter::<S>::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        BloomFilter::<S>::capacity(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        BloomFilter::<S>::size_in_bytes(self)
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        BloomFilter::<S>::expected_fpr(self)
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> MutableFilter<T> for BloomFilter<S> {
+    type Error = Infallible;
+
+    fn insert(&mut self, item: &T) -> Result<(), Infallible> {
+        self.add(item);
+        Ok(())
+    }
+}
+
+struct Params {
+    expected_items: usize,
+    num_items: usize,
+    num_bits: usize,
+    num_hashes: usize,
+    mode: IndexingMode,
+}
+
+impl Params {
+    fn decode(params: &[u64]) -> Result<Params, SerializationError> {
+        let [expected_items, num_items, num_bits, num_hashes, mode] = params[..] else {
+            return Err(SerializationError::InvalidParameters("a bloom filter has 5 parameters"));
+        };
+        let mode = match mode {
+            0 => IndexingMode::Seeded,
+            1 => IndexingMode::DoubleHashing,
+            _ => return Err(SerializationError::InvalidParameters("unknown indexing mode")),
+        };
+        let num_bits = to_usize(num_bits)?;
+        let num_hashes = to_usize(num_hashes)?;
+        if num_bits == 0 || num_hashes == 0 {
+            return Err(SerializationError::InvalidParameters("bloom filter size out of range"));
+        }
+        Ok(Params { expected_items: to_usize(expected_items)?, num_items: to_usize(num_items)?, num_bits, num_hashes, mode })
+    }
+}
+
+// Names of the parameter words, in the order to_bytes writes them. serde_support uses them as field names.
+pub(crate) const PARAM_NAMES: [&str; 5] = ["expected_items", "num_items", "num_bits", "num_hashes", "mode"];
+
+// The indexing mode is saved as 0 for seeded and 1 for double hashing, and the payload is the bit
+// vector. The seeds are derived again from the filter seed.
+impl<S: SerializableHasher> BloomFilter<S> {
+    /// Serializes the filter in the format described in serialization.rs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mode = match self.mode {
+            IndexingMode::Seeded => 0,
+            IndexingMode::DoubleHashing => 1,
+        };
+        let params: [u64; PARAM_NAMES.len()] = [self.expected_items as u64, self.num_items as u64, self.size as u64, self.num_hashes as u64, mode];
+        serialization::encode(FilterKind::Bloom, S::ID, self.hash_builder.state(), self.seed, &params, &words_to_bytes(&self.bit_vec))
+    }
+
+    /// Writes to_bytes() to writer.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        serialization::write_all(writer, &self.to_bytes())
     }
 
-    // Add an item to the Bloom filter
-    fn add<T: Hash>(&mut self, item: &T) {
-        for i in &self.seeds {
-            let index = self.hash(item, *i);
-            self.bit_vec[index] = true;
+    // Hasher state written to the header, for containers that store stages under their own header.
+    pub(crate) fn hasher_state(&self) -> u64 {
+        self.hash_builder.state()
+    }
+
+    /// Loads a filter written by to_bytes, checking its framing, checksum and parameters.
+    pub fn from_bytes_with_hasher(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let encoded = serialization::decode(bytes, FilterKind::Bloom, S::ID)?;
+        let params = Params::decode(&encoded.params)?;
+        // checked before allocating anything of that size
+        if encoded.payload.len() / 8 != params.num_bits.div_ceil(64) {
+            return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
         }
+        let mut filter = Self::with_mode_and_hasher(params.expected_items, params.num_bits, params.num_hashes, params.mode, encoded.seed, S::from_state(encoded.hasher_state));
+        bytes_to_words(encoded.payload, &mut filter.bit_vec)?;
+        filter.num_items = params.num_items;
+        Ok(filter)
+    }
 
+    /// Reads all of reader and loads it with from_bytes_with_hasher.
+    pub fn read_from_with_hasher<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
     }
+}
+
+impl BloomFilter {
+    /// Loads a filter built with the default hasher, see from_bytes_with_hasher.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(bytes)
+    }
+
+    /// Reads all of reader and loads it with from_bytes.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
+    }
+}
+
+/// Read-only bloom filter answering queries straight from the bytes written by BloomFilter::to_bytes,
+/// e.g. a memory-mapped file, without copying the bit vector. The bytes must start at an 8-byte
+/// aligned address. Opening checks the checksum, which reads the whole buffer once;
+/// from_bytes_unchecked skips it.
+pub struct BloomFilterRef<'a, S = DefaultBuildHasher> {
+    bit_vec: &'a [u64],
+    seeds: Vec<u64>,
+    seed: u64,
+    num_hashes: usize,
+    mode: IndexingMode,
+    size: usize,
+    expected_items: usize,
+    num_items: usize,
+    hash_builder: S,
+}
 
-    // Check if an item might be in the Bloom filter
-    fn contains<T: Hash>(&self, item: &T) -> bool {
-        for i in &self.seeds {
-            if !self.bit_vec[self.hash(item, *i)] {
-                return false;
-            }
+impl<'a> BloomFilterRef<'a> {
+    /// Opens a view over a filter built with the default hasher, see from_bytes_with_hasher.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(bytes)
+    }
+
+    /// Opens a view over a filter built with the default hasher, see from_bytes_unchecked_with_hasher.
+    pub fn from_bytes_unchecked(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_unchecked_with_hasher(bytes)
+    }
+}
+
+impl<'a, S: SerializableHasher> BloomFilterRef<'a, S> {
+    /// Opens a view over bytes written by BloomFilter::to_bytes without copying the bit vector.
+    pub fn from_bytes_with_hasher(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::open(serialization::decode(bytes, FilterKind::Bloom, S::ID)?)
+    }
+
+    /// Same as from_bytes_with_hasher without verifying the checksum, so opening does not read the
+    /// whole buffer. The header, lengths and alignment are still checked and queries stay in bounds,
+    /// but a corrupted bit vector gives wrong answers instead of an error: only use it on bytes known
+    /// to be intact, e.g. opened once with from_bytes after they were written.
+    pub fn from_bytes_unchecked_with_hasher(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::open(serialization::decode_unchecked(bytes, FilterKind::Bloom, S::ID)?)
+    }
+
+    fn open(encoded: Encoded<'a>) -> Result<Self, SerializationError> {
+        let params = Params::decode(&encoded.params)?;
+        let bit_vec = cast_words(encoded.payload)?;
+        if bit_vec.len() != params.num_bits.div_ceil(64) {
+            return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
         }
-        true
+        Ok(BloomFilterRef {
+            bit_vec,
+            seeds: derive_seeds(params.mode, encoded.seed, params.num_hashes),
+            seed: encoded.seed,
+            num_hashes: params.num_hashes,
+            mode: params.mode,
+            size: params.num_bits,
+            expected_items: params.expected_items,
+            num_items: params.num_items,
+            hash_builder: S::from_state(encoded.hasher_state),
+        })
+    }
+
+    /// Same as BloomFilter::contains.
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.contains_hash(self.hash_builder.hash_one(item))
     }
 
+    /// Same as BloomFilter::contains_hash.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        (0..self.num_hashes).all(|i| bit_is_set(self.bit_vec, bit_index(self.mode, &self.seeds, self.size, hash, i)))
+    }
+
+    /// Number of items added before the filter was saved.
+    pub fn len(&self) -> usize {
+        self.num_items
+    }
+
+    /// True if nothing was added before the filter was saved.
+    pub fn is_empty(&self) -> bool {
+        self.num_items == 0
+    }
+
+    /// Number of items the filter was sized for.
+    pub fn capacity(&self) -> usize {
+        self.expected_items
+    }
+
+    /// 'm', the number of bits in the filter.
+    pub fn num_bits(&self) -> usize {
+        self.size
+    }
+
+    /// 'k', the number of hash functions.
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    /// Seed the filter was built with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Bytes of the borrowed bit vector.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.bit_vec)
+    }
+
+    /// False positive rate expected with the items added before the filter was saved.
+    pub fn expected_fpr(&self) -> f64 {
+        fpr_estimate(self.num_hashes, self.num_items, self.size)
+    }
+}
+
+impl<T: Hash + ?Sized, S: SerializableHasher> Filter<T> for BloomFilterRef<'_, S> {
+    fn contains(&self, item: &T) -> bool {
+        BloomFilterRef::<S>::contains(self, item)
+    }
+
+    fn len(&self) -> usize {
+        BloomFilterRef::<S>::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        BloomFilterRef::<S>::capacity(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        BloomFilterRef::<S>::size_in_bytes(self)
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        BloomFilterRef::<S>::expected_fpr(self)
+    }
 }
 
 // The test only works for adding natural numbers from 1 to expected_items for simplicity. 
@@ -79,9 +512,8 @@ fn compute_mean_and_variance(times: &[Duration]) -> (f64, f64) {
 
 fn test_bloom_f_with_specified_num_of_items(expected_items: usize){
     //carry out a single test
-    let expected_items = expected_items;
     let mut filter = BloomFilter::new(expected_items);
-    let bits_per_item=filter.size as f64/expected_items as f64;
+    let bits_per_item=(filter.size_in_bytes()*8) as f64/expected_items as f64;
     println!("Bloom filter space usage: bit/item is {:?}", bits_per_item);
     let bloom_f_insertion_start_time = Instant::now();
     for item in 1..=expected_items{
@@ -149,7 +581,103 @@ fn test_bloom_f_with_specified_num_of_items(expected_items: usize){
     println!("BF: Positive Check for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", expected_items, pos_check_mean, pos_check_variance);
 }
 
+const BENCHMARK_SEED: u64 = 0x5EED;
+
+// Same items and sizing in both indexing modes, to check that double hashing keeps the false
+// positive rate of the seeded hash functions.
+fn compare_indexing_modes(expected_items: usize){
+    for mode in [IndexingMode::Seeded, IndexingMode::DoubleHashing] {
+        let size = optimal_num_bits(expected_items, DEFAULT_FALSE_POSITIVE_RATE);
+        let num_hashes = optimal_num_hashes(size, expected_items);
+        let mut filter = BloomFilter::with_mode_and_hasher(expected_items, size, num_hashes, mode, BENCHMARK_SEED, DefaultBuildHasher::default());
+        let insertion_start_time = Instant::now();
+        for item in 1..=expected_items{
+            filter.add(&item);
+        }
+        let insertion_duration = insertion_start_time.elapsed();
+        let mut false_positive_num=0;
+        let neg_query_start_time = Instant::now();
+        for item in expected_items+1..=expected_items+expected_items{
+            if filter.contains(&item){false_positive_num+=1;}
+        }
+        let neg_query_duration = neg_query_start_time.elapsed();
+        println!("Bloom Filter ({:?}) False Positive Rate is ({:?} items) : {:?}, expected {:.6}",mode,expected_items,false_positive_num as f64/expected_items as f64,filter.expected_fpr());
+        println!("Bloom Filter ({:?}) Construction Time per item: {:?}, neg query Duration per item: {:?}",mode,insertion_duration/expected_items as u32,neg_query_duration/expected_items as u32);
+    }
+}
+
+/// Benchmarks the bloom filter and prints space, false positive rate and timings.
 pub fn test_bloom_filters(){
     test_bloom_f_with_specified_num_of_items(996147);
+    compare_indexing_modes(996147);
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::tests::{assert_rejects_corruption, assert_round_trips, at_offset};
+
+    fn filled(mut filter: BloomFilter) -> BloomFilter {
+        for i in 0..1000u32 {
+            filter.add(&i);
+        }
+        filter
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        for filter in [filled(BloomFilter::with_seed(1000, 0.01, 7)), filled(BloomFilter::with_double_hashing_and_seed(1000, 0.01, 7))] {
+            let loaded = assert_round_trips(&filter.to_bytes(), BloomFilter::from_bytes, |bytes| BloomFilter::read_from(bytes), |filter, out| filter.write_to(out));
+            assert_eq!(loaded.len(), filter.len());
+            assert!((0..2000u32).all(|i| loaded.contains(&i) == filter.contains(&i)));
+        }
+    }
+
+    #[test]
+    fn round_trips_with_more_hashes_than_bits() {
+        let mut filter = BloomFilter::with_params_and_seed(10, 8, 10, 1);
+        filter.add(&3u32);
+        let loaded = BloomFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert_eq!(loaded.to_bytes(), filter.to_bytes());
+        assert!(loaded.contains(&3u32));
+    }
 
+    #[test]
+    fn view_answers_like_the_filter() {
+        for filter in [filled(BloomFilter::with_seed(1000, 0.01, 7)), filled(BloomFilter::with_double_hashing_and_seed(1000, 0.01, 7))] {
+            at_offset(&filter.to_bytes(), 0, |bytes| {
+                let view = BloomFilterRef::from_bytes(bytes).unwrap();
+                assert_eq!(view.len(), filter.len());
+                assert!((0..2000u32).all(|i| view.contains(&i) == filter.contains(&i)));
+            });
+        }
+    }
+
+    #[test]
+    fn view_rejects_misaligned_or_corrupted_bytes() {
+        let bytes = filled(BloomFilter::with_seed(1000, 0.01, 7)).to_bytes();
+        for offset in [1, 4] {
+            at_offset(&bytes, offset, |bytes| assert!(matches!(BloomFilterRef::from_bytes(bytes), Err(SerializationError::Misaligned))));
+        }
+        at_offset(&bytes, 0, |bytes| assert_rejects_corruption(bytes, |bytes| BloomFilterRef::from_bytes(bytes).map(|_| ())));
+    }
+
+    #[test]
+    fn unchecked_view_skips_only_the_checksum() {
+        let filter = filled(BloomFilter::with_seed(1000, 0.01, 7));
+        let mut bytes = filter.to_bytes();
+        at_offset(&bytes, 0, |bytes| {
+            let view = BloomFilterRef::from_bytes_unchecked(bytes).unwrap();
+            assert!((0..2000u32).all(|i| view.contains(&i) == filter.contains(&i)));
+        });
+        at_offset(&bytes, 4, |bytes| assert!(matches!(BloomFilterRef::from_bytes_unchecked(bytes), Err(SerializationError::Misaligned))));
+        let checksum = bytes.len() - 4;
+        bytes[checksum] ^= 1;
+        at_offset(&bytes, 0, |bytes| {
+            assert!(matches!(BloomFilterRef::from_bytes(bytes), Err(SerializationError::ChecksumMismatch { .. })));
+            assert!(BloomFilterRef::from_bytes_unchecked(bytes).is_ok());
+            assert!(matches!(BloomFilterRef::from_bytes_unchecked(&bytes[..checksum]), Err(SerializationError::Truncated)));
+        });
+    }
 }
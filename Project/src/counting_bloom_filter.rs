@@ -0,0 +1,548 @@
+use std::hash::{BuildHasher, Hash};
+use std::convert::Infallible;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+use crate::bloom_filter::{self, bit_index, derive_seeds, fpr_estimate, IndexingMode};
+use crate::error::SerializationError;
+use crate::filter::{DeletableFilter, Filter, MutableFilter};
+use crate::hasher::DefaultBuildHasher;
+use crate::seed::random_seed;
+use crate::serialization::{self, bytes_to_words, to_usize, words_to_bytes, FilterKind, SerializableHasher};
+
+/// 4-bit counters overflow with probability about 1.37e-15 * m at the optimal k (Fan et al.).
+pub const DEFAULT_COUNTER_BITS: usize = 4;
+
+/// Bloom filter with a small counter in place of each bit, so items can be removed again. Sizing,
+/// seeds and indexing are those of BloomFilter: the same seed and items pick the same positions.
+///
+/// A counter that reaches its maximum saturates and sticks there: it is never incremented past it
+/// nor decremented again, because its true value is no longer known. Such a counter can keep a
+/// removed item looking present, but never makes a present item look absent.
+pub struct CountingBloomFilter<S = DefaultBuildHasher> {
+    counters: Vec<u64>,// counter i lives in bits i * counter_bits.. of the packed words
+    counter_bits: usize,
+    max_count: u64,
+    seeds: Vec<u64>,// empty with IndexingMode::DoubleHashing
+    seed: u64,
+    num_hashes: usize,
+    mode: IndexingMode,
+    size: usize,
+    expected_items: usize,
+    num_items: usize,
+    num_saturated: usize,
+    hash_builder: S,
+}
+
+impl CountingBloomFilter {
+    /// Initialize a new CountingBloomFilter with the default false positive rate of 0.0074 and 4-bit counters
+    pub fn new(expected_item_size: usize) -> CountingBloomFilter {
+        Self::with_fpr(expected_item_size, bloom_filter::DEFAULT_FALSE_POSITIVE_RATE)
+    }
+
+    /// Initialize a new CountingBloomFilter with a target false positive rate and 4-bit counters
+    pub fn with_fpr(expected_item_size: usize, false_positive_rate: f64) -> CountingBloomFilter {
+        Self::with_counter_bits(expected_item_size, false_positive_rate, DEFAULT_COUNTER_BITS)
+    }
+
+    /// Initialize a new CountingBloomFilter with a target false positive rate and counters of counter_bits bits
+    pub fn with_counter_bits(expected_item_size: usize, false_positive_rate: f64, counter_bits: usize) -> CountingBloomFilter {
+        Self::with_counter_bits_and_seed(expected_item_size, false_positive_rate, counter_bits, random_seed())
+    }
+
+    /// Same as with_counter_bits with a fixed seed.
+    pub fn with_counter_bits_and_seed(expected_item_size: usize, false_positive_rate: f64, counter_bits: usize, seed: u64) -> CountingBloomFilter {
+        Self::with_fpr_and_hasher(expected_item_size, false_positive_rate, counter_bits, seed, DefaultBuildHasher::default())
+    }
+
+    /// Initialize a new CountingBloomFilter with a target false positive rate and a fixed seed. The
+    /// counters sit where BloomFilter::with_seed would set its bits.
+    pub fn with_seed(expected_item_size: usize, false_positive_rate: f64, seed: u64) -> CountingBloomFilter {
+        Self::with_fpr_and_hasher(expected_item_size, false_positive_rate, DEFAULT_COUNTER_BITS, seed, DefaultBuildHasher::default())
+    }
+}
+
+impl<S: BuildHasher> CountingBloomFilter<S> {
+    /// Initialize a new CountingBloomFilter with a target false positive rate, hashing items with hash_builder
+    pub fn with_fpr_and_hasher(expected_item_size: usize, false_positive_rate: f64, counter_bits: usize, seed: u64, hash_builder: S) -> Self {
+        let size = bloom_filter::optimal_num_bits(expected_item_size, false_positive_rate);
+        let num_hashes = bloom_filter::optimal_num_hashes(size, expected_item_size);
+        Self::with_params_and_hasher(expected_item_size, size, num_hashes, counter_bits, seed, hash_builder)
+    }
+
+    /// Initialize a new CountingBloomFilter with explicit 'm' counters and 'k', hashing items with hash_builder
+    pub fn with_params_and_hasher(expected_item_size: usize, num_counters: usize, num_hashes: usize, counter_bits: usize, seed: u64, hash_builder: S) -> Self {
+        Self::with_mode_and_hasher(expected_item_size, num_counters, num_hashes, counter_bits, IndexingMode::Seeded, seed, hash_builder)
+    }
+
+    /// Initialize a new CountingBloomFilter with explicit 'm', 'k', counter width and indexing mode
+    pub fn with_mode_and_hasher(expected_item_size: usize, num_counters: usize, num_hashes: usize, counter_bits: usize, mode: IndexingMode, seed: u64, hash_builder: S) -> Self {
+        assert!(num_counters > 0, "a counting bloom filter needs at least one counter");
+        assert!(num_hashes > 0, "a counting bloom filter needs at least one hash function");
+        // a width dividing 64 keeps every counter inside one word
+        assert!(matches!(counter_bits, 2 | 4 | 8 | 16 | 32), "counters must be 2, 4, 8, 16 or 32 bits wide");
+        let num_words = num_counters.checked_mul(counter_bits).expect("counting bloom filter too large").div_ceil(64);
+        CountingBloomFilter {
+            counters: vec![0u64; num_words],
+            counter_bits,
+            max_count: (1u64 << counter_bits) - 1,
+            seeds: derive_seeds(mode, seed, num_hashes),
+            seed,
+            num_hashes,
+            mode,
+            size: num_counters,
+            expected_items: expected_item_size,
+            num_items: 0,
+            num_saturated: 0,
+            hash_builder,
+        }
+    }
+
+    fn index(&self, hash: u64, i: usize) -> usize {
+        bit_index(self.mode, &self.seeds, self.size, hash, i)
+    }
+
+    fn counter(&self, index: usize) -> u64 {
+        let offset = index * self.counter_bits;
+        (self.counters[offset >> 6] >> (offset & 63)) & self.max_count
+    }
+
+    fn set_counter(&mut self, index: usize, value: u64) {
+        let offset = index * self.counter_bits;
+        let word = &mut self.counters[offset >> 6];
+        *word = (*word & !(self.max_count << (offset & 63))) | (value << (offset & 63));
+    }
+
+    /// Add an item to the filter
+    pub fn add<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.insert_hash(self.hash_builder.hash_one(item));
+    }
+
+    /// Check if an item might be in the filter
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.contains_hash(self.hash_builder.hash_one(item))
+    }
+
+    /// Remove one occurrence of an item. Returns false, leaving the filter unchanged, if the item is
+    /// definitely not in it. Removing an item that was never added may remove a colliding one instead.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        self.remove_hash(self.hash_builder.hash_one(item))
+    }
+
+    /// Upper bound on how many times an item was added: the smallest of its counters. Saturated
+    /// counters read as their maximum.
+    pub fn count_estimate<T: Hash + ?Sized>(&self, item: &T) -> usize {
+        self.count_estimate_hash(self.hash_builder.hash_one(item))
+    }
+
+    /// Same as add for a caller that already hashed the item with this filter's hasher.
+    pub fn insert_hash(&mut self, hash: u64) {
+        for i in 0..self.num_hashes {
+            let index = self.index(hash, i);
+            let count = self.counter(index);
+            if count < self.max_count {
+                self.set_counter(index, count + 1);
+                if count + 1 == self.max_count {
+                    self.num_saturated += 1;
+                }
+            }
+        }
+        self.num_items += 1;
+    }
+
+    /// Same as contains for a caller that already hashed the item with this filter's hasher.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        (0..self.num_hashes).all(|i| self.counter(self.index(hash, i)) != 0)
+    }
+
+    /// Same as remove for a caller that already hashed the item with this filter's hasher.
+    pub fn remove_hash(&mut self, hash: u64) -> bool {
+        if !self.contains_hash(hash) {
+            return false;
+        }
+        for i in 0..self.num_hashes {
+            let index = self.index(hash, i);
+            let count = self.counter(index);
+            // the check above leaves count at 0 only when two of the k positions coincide and an
+            // earlier wrong removal already took the counter down
+            if count != 0 && count != self.max_count {
+                self.set_counter(index, count - 1);
+            }
+        }
+        self.num_items = self.num_items.saturating_sub(1);
+        true
+    }
+
+    /// Same as count_estimate for a caller that already hashed the item with this filter's hasher.
+    pub fn count_estimate_hash(&self, hash: u64) -> usize {
+        (0..self.num_hashes).map(|i| self.counter(self.index(hash, i))).min().unwrap_or(0) as usize
+    }
+
+    /// Number of add calls minus successful removals.
+    pub fn len(&self) -> usize {
+        self.num_items
+    }
+
+    /// True if no item is left in the filter.
+    pub fn is_empty(&self) -> bool {
+        self.num_items == 0
+    }
+
+    /// Number of items the filter was sized for.
+    pub fn capacity(&self) -> usize {
+        self.expected_items
+    }
+
+    /// 'm', the number of counters in the filter.
+    pub fn num_counters(&self) -> usize {
+        self.size
+    }
+
+    /// 'k', the number of hash functions.
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    /// Width of each counter in bits.
+    pub fn counter_bits(&self) -> usize {
+        self.counter_bits
+    }
+
+    /// How counter positions are derived from an item's hash.
+    pub fn indexing_mode(&self) -> IndexingMode {
+        self.mode
+    }
+
+    /// Seed the filter was built with; passing it back to a constructor rebuilds the same filter.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of counters stuck at their maximum.
+    pub fn saturated_counters(&self) -> usize {
+        self.num_saturated
+    }
+
+    /// True once any counter has saturated. From then on removals may leave false positives behind,
+    /// and a wider counter_bits is needed to avoid it.
+    pub fn has_overflowed(&self) -> bool {
+        self.num_saturated > 0
+    }
+
+    /// Bytes of the packed counters.
+    pub fn size_in_bytes(&self) -> usize {
+        self.counters.len() * std::mem::size_of::<u64>()
+    }
+
+    /// False positive rate expected with the items currently in the filter.
+    pub fn expected_fpr(&self) -> f64 {
+        fpr_estimate(self.num_hashes, self.num_items, self.size)
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> Filter<T> for CountingBloomFilter<S> {
+    fn contains(&self, item: &T) -> bool {
+        CountingBloomFilter::<S>::contains(self, item)
+    }
+
+    fn len(&self) -> usize {
+        CountingBloomFilter::<S>::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        CountingBloomFilter::<S>::capacity(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        CountingBloomFilter::<S>::size_in_bytes(self)
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        CountingBloomFilter::<S>::expected_fpr(self)
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> MutableFilter<T> for CountingBloomFilter<S> {
+    type Error = Infallible;
+
+    fn insert(&mut self, item: &T) -> Result<(), Infallible> {
+        self.add(item);
+        Ok(())
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> DeletableFilter<T> for CountingBloomFilter<S> {
+    fn remove(&mut self, item: &T) -> bool {
+        CountingBloomFilter::<S>::remove(self, item)
+    }
+}
+
+// Names of the parameter words, in the order to_bytes writes them. serde_support uses them as field names.
+pub(crate) const PARAM_NAMES: [&str; 7] = ["expected_items", "num_items", "num_counters", "num_hashes", "mode", "counter_bits", "num_saturated"];
+
+// The indexing mode is saved as in BloomFilter and the payload is the packed counters.
+impl<S: SerializableHasher> CountingBloomFilter<S> {
+    /// Serializes the filter in the format described in serialization.rs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mode = match self.mode {
+            IndexingMode::Seeded => 0,
+            IndexingMode::DoubleHashing => 1,
+        };
+        let params: [u64; PARAM_NAMES.len()] = [
+            self.expected_items as u64,
+            self.num_items as u64,
+            self.size as u64,
+            self.num_hashes as u64,
+            mode,
+            self.counter_bits as u64,
+            self.num_saturated as u64,
+        ];
+        serialization::encode(FilterKind::CountingBloom, S::ID, self.hash_builder.state(), self.seed, &params, &words_to_bytes(&self.counters))
+    }
+
+    /// Writes to_bytes() to writer.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        serialization::write_all(writer, &self.to_bytes())
+    }
+
+    /// Loads a filter written by to_bytes, checking its framing, checksum and parameters.
+    pub fn from_bytes_with_hasher(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let encoded = serialization::decode(bytes, FilterKind::CountingBloom, S::ID)?;
+        let [expected_items, num_items, num_counters, num_hashes, mode, counter_bits, num_saturated] = encoded.params[..] else {
+            return Err(SerializationError::InvalidParameters("a counting bloom filter has 7 parameters"));
+        };
+        let mode = match mode {
+            0 => IndexingMode::Seeded,
+            1 => IndexingMode::DoubleHashing,
+            _ => return Err(SerializationError::InvalidParameters("unknown indexing mode")),
+        };
+        let num_counters = to_usize(num_counters)?;
+        let num_hashes = to_usize(num_hashes)?;
+        let counter_bits = to_usize(counter_bits)?;
+        if num_counters == 0 || num_hashes == 0 || !matches!(counter_bits, 2 | 4 | 8 | 16 | 32) {
+            return Err(SerializationError::InvalidParameters("counting bloom filter parameters out of range"));
+        }
+        // checked before allocating anything of that size
+        if num_counters.checked_mul(counter_bits).map(|bits| bits.div_ceil(64)) != Some(encoded.payload.len() / 8) {
+            return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
+        }
+        let mut filter = Self::with_mode_and_hasher(to_usize(expected_items)?, num_counters, num_hashes, counter_bits, mode, encoded.seed, S::from_state(encoded.hasher_state));
+        bytes_to_words(encoded.payload, &mut filter.counters)?;
+        filter.num_items = to_usize(num_items)?;
+        filter.num_saturated = to_usize(num_saturated)?;
+        Ok(filter)
+    }
+
+    /// Reads all of reader and loads it with from_bytes_with_hasher.
+    pub fn read_from_with_hasher<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
+    }
+}
+
+impl CountingBloomFilter {
+    /// Loads a filter built with the default hasher, see from_bytes_with_hasher.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(bytes)
+    }
+
+    /// Reads all of reader and loads it with from_bytes.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
+    }
+}
+
+// The test only works for adding natural numbers from 1 to expected_items for simplicity.
+// The test logic needs to be changed if user wants to check for adding different kinds of numbers.
+
+fn compute_mean_and_variance(times: &[Duration]) -> (f64, f64) {
+    let times_in_secs: Vec<f64> = times.iter()
+        .map(|d| d.as_secs_f64())
+        .collect();
+
+    let mean = times_in_secs.iter().sum::<f64>() / times_in_secs.len() as f64;
+
+    let variance = times_in_secs.iter()
+        .map(|time| (time - mean).powi(2))
+        .sum::<f64>() / times_in_secs.len() as f64;
+
+    (mean, variance)
+}
+
+fn test_counting_bloom_f_with_specified_num_of_items(expected_items: usize){
+    //carry out a single test
+    let mut filter = CountingBloomFilter::new(expected_items);
+    let bits_per_item=(filter.size_in_bytes()*8) as f64/expected_items as f64;
+    println!("Counting Bloom filter space usage: bit/item is {:?}", bits_per_item);
+    let counting_bloom_f_insertion_start_time = Instant::now();
+    for item in 1..=expected_items{
+        filter.add(&item);
+    }//insert items
+    let counting_bloom_f_insertion_duration = counting_bloom_f_insertion_start_time.elapsed();
+    println!("Counting Bloom Filter Construction Time per item for {:?} items: {:?}",expected_items,counting_bloom_f_insertion_duration/expected_items as u32);
+    println!("Counting Bloom Filter saturated counters after construction: {:?}",filter.saturated_counters());
+
+    let mut counting_bloom_f_false_positive_num=0;
+    let counting_bloom_f_neg_query_start_time = Instant::now();
+    for item in expected_items+1..=expected_items+expected_items{
+        if filter.contains(&item){counting_bloom_f_false_positive_num+=1;}
+    }
+    let counting_bloom_f_neg_query_duration = counting_bloom_f_neg_query_start_time.elapsed();
+    let counting_bloom_fpr= counting_bloom_f_false_positive_num as f64/expected_items as f64;
+    println!("Counting Bloom Filter False Positive Rate is ({:?} items) : {:?}",expected_items,counting_bloom_fpr);
+    println!("Counting Bloom Filter query Duration per item for {:?} neg items: {:?}",expected_items,counting_bloom_f_neg_query_duration/expected_items as u32);
+    let mut counting_bloom_f_true_positive_num=0;
+    let counting_bloom_f_pos_query_start_time = Instant::now();
+    for item in 1..=expected_items{
+        if filter.contains(&item){counting_bloom_f_true_positive_num+=1;}
+    }
+    let counting_bloom_f_pos_query_duration = counting_bloom_f_pos_query_start_time.elapsed();
+    let counting_bloom_tpr= counting_bloom_f_true_positive_num as f64/expected_items as f64;
+    println!("Counting Bloom Filter True Positive Rate is ({:?} items) : {:?}",expected_items,counting_bloom_tpr);
+    println!("Counting Bloom Filter query Duration per item for {:?} pos items: {:?}",expected_items,counting_bloom_f_pos_query_duration/expected_items as u32);
+
+    //deletion time
+    let counting_bloom_f_delete_start_time = Instant::now();
+    for item in 1..=expected_items{
+        filter.remove(&item);
+    }
+    let counting_bloom_f_delete_duration = counting_bloom_f_delete_start_time.elapsed();
+    println!("Counting Bloom Filter deletion time per item for {:?} items: {:?}",expected_items,counting_bloom_f_delete_duration/expected_items as u32);
+
+    //deleted items should be definitely not in the filter unless a counter saturated.
+    let mut counting_bloom_f_left_num=0;
+    for item in 1..=expected_items{
+        if filter.contains(&item){counting_bloom_f_left_num+=1;}
+    }
+    println!("Counting Bloom Filter fpr on inserted items after deletion: {:?}",counting_bloom_f_left_num as f64/expected_items as f64);
+
+    //carry out several tests for benchmark
+    let test_num = 20;
+    let mut construct_times: Vec<Duration> = Vec::with_capacity(test_num);
+    let mut pos_check_times: Vec<Duration> = Vec::with_capacity(test_num);
+    let mut neg_check_times: Vec<Duration> = Vec::with_capacity(test_num);
+    let mut deletion_times: Vec<Duration> = Vec::with_capacity(test_num);
+
+    for _ in 0..test_num{
+
+        let mut filter = CountingBloomFilter::new(expected_items);
+        let counting_bloom_f_insertion_start_time = Instant::now();
+        for item in 1..=expected_items{
+            filter.add(&item);
+        }//insert items
+        let counting_bloom_f_insertion_duration = counting_bloom_f_insertion_start_time.elapsed();
+        construct_times.push(counting_bloom_f_insertion_duration);
+
+        let counting_bloom_f_neg_query_start_time = Instant::now();
+        for item in expected_items+1..=expected_items+expected_items{
+            filter.contains(&item);
+        }
+        let counting_bloom_f_neg_query_duration = counting_bloom_f_neg_query_start_time.elapsed();
+        neg_check_times.push(counting_bloom_f_neg_query_duration);
+
+        let counting_bloom_f_pos_query_start_time = Instant::now();
+        for item in 1..=expected_items{
+            filter.contains(&item);
+        }
+        let counting_bloom_f_pos_query_duration = counting_bloom_f_pos_query_start_time.elapsed();
+        pos_check_times.push(counting_bloom_f_pos_query_duration);
+
+        let counting_bloom_f_delete_start_time = Instant::now();
+        for item in 1..=expected_items{
+            filter.remove(&item);
+        }
+        let counting_bloom_f_delete_duration = counting_bloom_f_delete_start_time.elapsed();
+        deletion_times.push(counting_bloom_f_delete_duration);
+    }
+    let (construct_mean, construct_variance) = compute_mean_and_variance(&construct_times);
+    let (neg_check_mean, neg_check_variance) = compute_mean_and_variance(&neg_check_times);
+    let (pos_check_mean, pos_check_variance) = compute_mean_and_variance(&pos_check_times);
+    let (deletion_mean, deletion_variance) = compute_mean_and_variance(&deletion_times);
+
+    println!("CBF: Construction for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", expected_items, construct_mean, construct_variance);
+    println!("CBF: Negative Check for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", expected_items, neg_check_mean, neg_check_variance);
+    println!("CBF: Positive Check for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", expected_items, pos_check_mean, pos_check_variance);
+    println!("CBF: Deletion for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", expected_items, deletion_mean, deletion_variance);
+}
+
+/// Benchmarks the counting bloom filter and prints space, false positive rate and timings.
+pub fn test_counting_bloom_filters(){
+    test_counting_bloom_f_with_specified_num_of_items(996147);
+    // same number of items as the bloom filter test, so the two can be compared line by line.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::tests::assert_round_trips;
+
+    fn filled() -> CountingBloomFilter {
+        let mut filter = CountingBloomFilter::with_counter_bits_and_seed(1000, 0.01, 4, 7);
+        for i in 0..1000u32 {
+            filter.add(&i);
+        }
+        filter.remove(&0u32);
+        filter
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let filter = filled();
+        let mut loaded = assert_round_trips(&filter.to_bytes(), CountingBloomFilter::from_bytes, |bytes| CountingBloomFilter::read_from(bytes), |filter, out| filter.write_to(out));
+        assert_eq!(loaded.len(), filter.len());
+        assert!((0..2000u32).all(|i| loaded.count_estimate(&i) == filter.count_estimate(&i)));
+        // the counters keep working after loading
+        assert!(loaded.remove(&1u32));
+    }
+
+    #[test]
+    fn removed_items_are_absent() {
+        let mut filter = CountingBloomFilter::with_counter_bits_and_seed(1000, 0.01, 4, 7);
+        for i in 0..100u32 {
+            filter.add(&i);
+        }
+        for i in 0..100u32 {
+            assert!(filter.remove(&i));
+        }
+        assert!(filter.is_empty());
+        assert!((0..100u32).all(|i| !filter.contains(&i)));
+        assert!(!filter.remove(&0u32));
+    }
+
+    #[test]
+    fn count_estimate_follows_repeated_adds() {
+        let mut filter = CountingBloomFilter::with_counter_bits_and_seed(1000, 0.01, 4, 7);
+        assert_eq!(filter.count_estimate(&"item"), 0);
+        for count in 1..=5 {
+            filter.add(&"item");
+            assert_eq!(filter.count_estimate(&"item"), count);
+        }
+        assert!(filter.remove(&"item"));
+        assert_eq!(filter.count_estimate(&"item"), 4);
+    }
+
+    #[test]
+    fn saturated_counters_stick_without_false_negatives() {
+        let mut filter = CountingBloomFilter::with_params_and_hasher(100, 64, 3, 2, 7, DefaultBuildHasher::default());
+        for i in 0..200u32 {
+            filter.add(&i);
+        }
+        assert!(filter.has_overflowed());
+        let saturated: Vec<usize> = (0..filter.num_counters()).filter(|&i| filter.counter(i) == filter.max_count).collect();
+        assert_eq!(filter.saturated_counters(), saturated.len());
+        assert!(!saturated.is_empty());
+        for i in 0..100u32 {
+            assert!(filter.remove(&i));
+        }
+        assert!(saturated.iter().all(|&i| filter.counter(i) == filter.max_count));
+        assert_eq!(filter.saturated_counters(), saturated.len());
+        assert!((100..200u32).all(|i| filter.contains(&i)));
+    }
+
+    #[test]
+    fn round_trips_with_more_hashes_than_counters() {
+        let mut filter = CountingBloomFilter::with_params_and_hasher(10, 8, 10, 4, 1, DefaultBuildHasher::default());
+        filter.add(&3u32);
+        let loaded = CountingBloomFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert_eq!(loaded.to_bytes(), filter.to_bytes());
+        assert!(loaded.contains(&3u32));
+    }
+}
@@ -1,114 +1,437 @@
-use rand::{random, Rng};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
 use std::time::{Duration, Instant};
-
-const BUCKET_SIZE: usize = 4;
-const FINGERPRINT_SIZE: usize = 10; //reduce the likelihood of collisions. instead of 8.
+use crate::error::{CuckooError, SerializationError};
+use crate::filter::{DeletableFilter, Filter, MutableFilter};
+use crate::hasher::{fmix64, DefaultBuildHasher};
+use crate::reduce::fastrange;
+use crate::seed::{random_seed, SplitMix64};
+use crate::serialization::{self, bytes_to_words, to_usize, words_to_bytes, FilterKind, SerializableHasher};
+
+/// Slots per bucket used by the constructors that are not given one.
+pub const DEFAULT_BUCKET_SIZE: usize = 4;
+/// Fingerprint bits used by CuckooFilter::new.
+pub const DEFAULT_FINGERPRINT_SIZE: usize = 10; //reduce the likelihood of collisions. instead of 8.
 const MAX_NUM_KICKS: usize = 500;
 
-struct CuckooFilter {
-    buckets: Vec<Vec<u16>>,
+/// Picks (fingerprint bits, bucket slots) for a target false positive rate, following the cuckoo
+/// filter paper: 2 slots above 0.2%, 4 slots down to 0.001%, 8 slots below that. The fingerprint
+/// is then sized so that the 2b comparisons of a lookup stay under the target: f = log2(2b / fpr).
+pub fn parameters_for_fpr(false_positive_rate: f64) -> (usize, usize) {
+    assert!(false_positive_rate > 0f64 && false_positive_rate < 1f64, "false positive rate must be in (0, 1)");
+    let bucket_size = if false_positive_rate > 0.002 {
+        2
+    } else if false_positive_rate > 0.00001 {
+        4
+    } else {
+        8
+    };
+    let fingerprint_size = (2f64 * bucket_size as f64 / false_positive_rate).log2().ceil() as usize;
+    (fingerprint_size.clamp(4, 32), bucket_size)
+}
+
+// Load factor reachable before inserts start failing, for each bucket size.
+fn max_load_factor(bucket_size: usize) -> f64 {
+    match bucket_size {
+        1 => 0.5,
+        2 => 0.84,
+        4 => 0.95,
+        _ => 0.98,
+    }
+}
+
+/// Smallest power of 2 number of buckets holding expected_items at the load factor reachable with
+/// bucket_size slots per bucket. A power of 2 keeps the xor in hash2 inside the table.
+pub fn num_buckets_for_capacity(expected_items: usize, bucket_size: usize) -> usize {
+    let min_buckets = (expected_items as f64 / (bucket_size as f64 * max_load_factor(bucket_size))).ceil() as usize;
+    min_buckets.max(1).next_power_of_two()
+}
+
+/// Fingerprints live in one flat bit-packed table: bucket i is the run of bucket_size * fingerprint_size
+/// bits starting at bit i * bucket_size * fingerprint_size. An all-zero slot is empty, so fingerprints
+/// are never 0. A bucket is at most 8 * 32 bits, so each of the two buckets probed by a lookup is read
+/// from one or two adjacent 64-bit words.
+pub struct CuckooFilter<S = DefaultBuildHasher> {
+    table: Vec<u64>,
     size: usize,
-    seed: u64,
+    fingerprint_size: usize,// bits per fingerprint, 4 to 32
+    bucket_size: usize,// slots per bucket, 1, 2, 4 or 8
+    seed: u64,// seed0, seed1 and the eviction choices are all derived from it
+    seed0: u64,
     seed1: u64,
+    kick_rng: SplitMix64,
+    kick_path: Vec<(usize, usize, u32)>,// (bucket, slot, evicted fingerprint) per eviction, reused across inserts
+    expected_items: usize,
+    num_items: usize,
+    hash_builder: S,
 }
 
 impl CuckooFilter {
-    fn new(size: usize) -> Self {
-        let buckets = vec![Vec::with_capacity(BUCKET_SIZE); size];
-        let mut rng = rand::thread_rng();
-        let seed = rng.gen::<u64>() | 1;  // Ensure the seed is odd.
-        let seed1 = rng.gen::<u64>() | 1;
-        CuckooFilter { buckets, size, seed, seed1 }
-    }
-
-    fn fingerprint<T:Hash>(&self, x: &T) -> u16 {
-        let mut s = DefaultHasher::new();
-        x.hash(&mut s);
-        let hash_value = s.finish();
+    /// size is the number of buckets and needs to be a power of 2.
+    pub fn new(size: usize) -> Self {
+        Self::with_size_and_seed(size, random_seed())
+    }
+
+    /// Same as new with a fixed seed.
+    pub fn with_size_and_seed(size: usize, seed: u64) -> Self {
+        Self::with_params_and_seed(size, DEFAULT_FINGERPRINT_SIZE, DEFAULT_BUCKET_SIZE, seed)
+    }
+
+    /// Picks fingerprint width and bucket size for the target fpr, and enough buckets to hold
+    /// expected_items at the load factor reachable with that bucket size. with_seed is the same with
+    /// a fixed seed.
+    pub fn with_capacity(expected_items: usize, false_positive_rate: f64) -> Self {
+        Self::with_seed(expected_items, false_positive_rate, random_seed())
+    }
+
+    /// size is the number of buckets and needs to be a power of 2.
+    pub fn with_params(size: usize, fingerprint_size: usize, bucket_size: usize) -> Self {
+        Self::with_params_and_seed(size, fingerprint_size, bucket_size, random_seed())
+    }
+
+    /// Same as with_params with a fixed seed.
+    pub fn with_params_and_seed(size: usize, fingerprint_size: usize, bucket_size: usize, seed: u64) -> Self {
+        Self::with_params_and_hasher(size, fingerprint_size, bucket_size, seed, DefaultBuildHasher::default())
+    }
+
+    /// Same as with_capacity with a fixed seed. The same seed and sequence of operations always give
+    /// the same table.
+    pub fn with_seed(expected_items: usize, false_positive_rate: f64, seed: u64) -> Self {
+        Self::with_capacity_and_hasher(expected_items, false_positive_rate, seed, DefaultBuildHasher::default())
+    }
+}
+
+impl<S: BuildHasher> CuckooFilter<S> {
+    /// Same as with_capacity, with an explicit seed and hashing items with hash_builder.
+    pub fn with_capacity_and_hasher(expected_items: usize, false_positive_rate: f64, seed: u64, hash_builder: S) -> Self {
+        let (fingerprint_size, bucket_size) = parameters_for_fpr(false_positive_rate);
+        let mut filter = Self::with_params_and_hasher(num_buckets_for_capacity(expected_items, bucket_size), fingerprint_size, bucket_size, seed, hash_builder);
+        filter.expected_items = expected_items;
+        filter
+    }
+
+    /// Same as with_params, hashing items with hash_builder instead of the default hasher. The
+    /// filter is sized for as many items as fit at the load factor reachable with bucket_size.
+    pub fn with_params_and_hasher(size: usize, fingerprint_size: usize, bucket_size: usize, seed: u64, hash_builder: S) -> Self {
+        assert!(size.is_power_of_two(), "number of buckets must be a power of 2, got {}", size);
+        assert!((4..=32).contains(&fingerprint_size), "fingerprint size must be between 4 and 32 bits");
+        assert!(matches!(bucket_size, 1 | 2 | 4 | 8), "bucket size must be 1, 2, 4 or 8");
+        // one spare word so that reading a slot can always look at the following word
+        let table = vec![0u64; (size * bucket_size * fingerprint_size).div_ceil(64) + 1];
+        let mut rng = SplitMix64::new(seed);
+        let seed0 = rng.next_odd();  // Ensure the seed is odd.
+        let seed1 = rng.next_odd();
+        let kick_rng = SplitMix64::new(rng.next_u64());
+        let expected_items = ((size * bucket_size) as f64 * max_load_factor(bucket_size)) as usize;
+        CuckooFilter { table, size, fingerprint_size, bucket_size, seed, seed0, seed1, kick_rng, kick_path: Vec::new(), expected_items, num_items: 0, hash_builder }
+    }
+
+    // Fingerprint and both buckets come from one 64-bit hash of the item.
+    fn fingerprint(&self, hash_value: u64) -> u32 {
         // Apply multiply-shift hashing
         let hashed = self.seed1.wrapping_mul(hash_value);
-        let shifted = hashed >> (64 - FINGERPRINT_SIZE); // Right shift to get the top 'FINGERPRINT_SIZE' bits
-        (shifted as u16) & ((1 << FINGERPRINT_SIZE) - 1)  // Mask to ensure only 'FINGERPRINT_SIZE' bits are used
+        let shifted = hashed >> (64 - self.fingerprint_size); // Right shift to get the top 'fingerprint_size' bits
+        let fp = (shifted & ((1u64 << self.fingerprint_size) - 1)) as u32;  // Mask to ensure only 'fingerprint_size' bits are used
+        fp.max(1)// 0 marks an empty slot
     }
 
-    fn hash<T: Hash>(&self, item: &T, seed: u64) -> usize {
-        let mut hasher = DefaultHasher::new();
-        item.hash(&mut hasher);
-        let hash = hasher.finish();
-        (((seed.wrapping_mul(hash)) >> 32) % self.size as u64) as usize//multiply shift
+    fn hash(&self, hash: u64, seed: u64) -> usize {
+        fastrange(seed.wrapping_mul(hash), self.size)//multiply shift
     }//Ensure the output is the same for each key(item) throughout insertion/lookup/deletion.
-    //This hash function performs better here than in bloom/blocked bloom filters since size is of power of 2.
+    //size is a power of 2, so fastrange simply keeps the top bits of the multiply-shift.
 
-    fn hash1(&self, x: &i32) -> usize {
-        self.hash(x, self.seed)
+    fn hash1(&self, hash: u64) -> usize {
+        self.hash(hash, self.seed0)
     }
 
-    fn hash2(&self, i1: usize, f: u16) -> usize {
-        let fingerprint_as_i32 = f as i32;
-        i1 ^ self.hash(&fingerprint_as_i32, self.seed)
-    }// hash(x) xor hash(fingerprint)
+    fn hash2(&self, i1: usize, f: u32) -> usize {
+        i1 ^ self.hash(fmix64(f as u64), self.seed0)
+    }// hash(x) xor hash(fingerprint). The fingerprint is remixed rather than rehashed with the hasher.
 
-    fn insert(&mut self, x: &i32) -> bool {
-        let f = self.fingerprint(x);  // Original fingerprint
-        let i1 = self.hash1(x);
-        let i2 = self.hash2(i1, f);
+    fn read_slot(&self, bucket: usize, slot: usize) -> u32 {
+        let pos = (bucket * self.bucket_size + slot) * self.fingerprint_size;
+        let (word, offset) = (pos / 64, pos % 64);
+        let mut value = self.table[word] >> offset;
+        if offset + self.fingerprint_size > 64 {
+            value |= self.table[word + 1] << (64 - offset);
+        }
+        (value & ((1u64 << self.fingerprint_size) - 1)) as u32
+    }
 
-        if self.buckets[i1].len() < BUCKET_SIZE {
-            self.buckets[i1].push(f);
-            return true;
+    fn write_slot(&mut self, bucket: usize, slot: usize, fp: u32) {
+        let pos = (bucket * self.bucket_size + slot) * self.fingerprint_size;
+        let (word, offset) = (pos / 64, pos % 64);
+        let mask = (1u64 << self.fingerprint_size) - 1;
+        self.table[word] = (self.table[word] & !(mask << offset)) | ((fp as u64) << offset);
+        if offset + self.fingerprint_size > 64 {
+            let shift = 64 - offset;
+            self.table[word + 1] = (self.table[word + 1] & !(mask >> shift)) | ((fp as u64) >> shift);
         }
-        if self.buckets[i2].len() < BUCKET_SIZE {
-            self.buckets[i2].push(f);
-            return true;
+    }
+
+    fn find_slot(&self, bucket: usize, fp: u32) -> Option<usize> {
+        (0..self.bucket_size).find(|&slot| self.read_slot(bucket, slot) == fp)
+    }
+
+    // Stores fp in a free slot of the bucket, if there is one.
+    fn try_place(&mut self, bucket: usize, fp: u32) -> bool {
+        match self.find_slot(bucket, 0) {
+            Some(slot) => {
+                self.write_slot(bucket, slot, fp);
+                true
+            }
+            None => false,
         }
+    }
+
+    /// Either stores the item or, if no room is found within MAX_NUM_KICKS evictions, undoes every
+    /// eviction and returns CuckooError::Full with the filter exactly as it was before the call.
+    pub fn insert<T: Hash + ?Sized>(&mut self, x: &T) -> Result<(), CuckooError> {
+        self.insert_hash(self.hash_builder.hash_one(x))
+    }
+
+    /// Checks if an item might be in the filter.
+    pub fn lookup<T: Hash + ?Sized>(&self, x: &T) -> bool {
+        self.contains_hash(self.hash_builder.hash_one(x))
+    }
 
+    /// Removes one copy of an item. Returns false if no matching fingerprint was found.
+    pub fn delete<T: Hash + ?Sized>(&mut self, x: &T) -> bool {
+        self.delete_hash(self.hash_builder.hash_one(x))
+    }
+
+    /// Same as insert for a caller that already hashed the item with this filter's hasher.
+    pub fn insert_hash(&mut self, hash: u64) -> Result<(), CuckooError> {
+        let f = self.fingerprint(hash);  // Original fingerprint
+        let i1 = self.hash1(hash);
+        let i2 = self.hash2(i1, f);
+
+        if self.try_place(i1, f) || self.try_place(i2, f) {
+            self.num_items += 1;
+            return Ok(());
+        }
+
+        // Kept so that a failed insert leaves the eviction choices of later inserts unchanged too.
+        let kick_rng = self.kick_rng;
         // Starting with initial indices i1 or i2
-        let mut i = if random() { i1 } else { i2 };
+        let mut i = if self.kick_rng.next_u64() & 1 == 0 { i1 } else { i2 };
         let mut current_fingerprint = f;  // Mutable copy of the fingerprint to be used for swapping
+        let mut path = std::mem::take(&mut self.kick_path);
+        path.clear();
 
         for _ in 0..MAX_NUM_KICKS {
-            let entry = rand::thread_rng().gen_range(0..self.buckets[i].len());
-            std::mem::swap(&mut current_fingerprint, &mut self.buckets[i][entry]);  // Swap current_fingerprint with the entry in bucket
+            let entry = fastrange(self.kick_rng.next_u64(), self.bucket_size);
+            let evicted = self.read_slot(i, entry);
+            self.write_slot(i, entry, current_fingerprint);  // Swap current_fingerprint with the entry in bucket
+            path.push((i, entry, evicted));
+            current_fingerprint = evicted;
             i = self.hash2(i, current_fingerprint);  // Recalculate index using the updated fingerprint
 
-            if self.buckets[i].len() < BUCKET_SIZE {
-                self.buckets[i].push(current_fingerprint);  // Push the swapped fingerprint into the new bucket
-                return true;
+            if self.try_place(i, current_fingerprint) {  // Put the swapped fingerprint into the new bucket
+                self.num_items += 1;
+                self.kick_path = path;
+                return Ok(());
             }
         }
-        false
+
+        // Put every evicted fingerprint back where it was, newest eviction first.
+        for &(bucket, slot, evicted) in path.iter().rev() {
+            self.write_slot(bucket, slot, evicted);
+        }
+        self.kick_path = path;
+        self.kick_rng = kick_rng;
+        Err(CuckooError::Full)
     }
 
 
-    fn lookup(&self, x: &i32) -> bool {
-        let f = self.fingerprint(x);
-        let i1 = self.hash1(x);
+    /// Same as lookup for a caller that already hashed the item with this filter's hasher.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        let f = self.fingerprint(hash);
+        let i1 = self.hash1(hash);
         let i2 = self.hash2(i1, f);
 
-        self.buckets[i1].contains(&f) || self.buckets[i2].contains(&f)
+        self.find_slot(i1, f).is_some() || self.find_slot(i2, f).is_some()
     }
 
-    fn delete(&mut self, x: &i32) -> bool {
-        let f = self.fingerprint(x);
-        let i1 = self.hash1(x);
+    /// Same as delete for a caller that already hashed the item with this filter's hasher.
+    pub fn delete_hash(&mut self, hash: u64) -> bool {
+        let f = self.fingerprint(hash);
+        let i1 = self.hash1(hash);
         let i2 = self.hash2(i1, f);
 
-        if let Some(index) = self.buckets[i1].iter().position(|&item| item == f) {
-            self.buckets[i1].remove(index);
-            return true;
+        for i in [i1, i2] {
+            if let Some(slot) = self.find_slot(i, f) {
+                self.write_slot(i, slot, 0);
+                self.num_items = self.num_items.saturating_sub(1);
+                return true;
+            }
         }
 
-        if let Some(index) = self.buckets[i2].iter().position(|&item| item == f) {
-            self.buckets[i2].remove(index);
-            return true;
+        false
+    }
+
+    /// Number of fingerprints stored.
+    pub fn len(&self) -> usize {
+        self.num_items
+    }
+
+    /// True if no fingerprint is stored.
+    pub fn is_empty(&self) -> bool {
+        self.num_items == 0
+    }
+
+    /// Number of items the filter was sized for: expected_items for with_capacity, otherwise the
+    /// slots that can be filled in practice (0.95 of them with 4 slots per bucket).
+    pub fn capacity(&self) -> usize {
+        self.expected_items
+    }
+
+    /// Number of fingerprint slots, num_buckets() * bucket_size().
+    pub fn num_slots(&self) -> usize {
+        self.size * self.bucket_size
+    }
+
+    /// Bits per fingerprint.
+    pub fn fingerprint_size(&self) -> usize {
+        self.fingerprint_size
+    }
+
+    /// Slots per bucket.
+    pub fn bucket_size(&self) -> usize {
+        self.bucket_size
+    }
+
+    /// Seed the filter was built with; passing it back to a constructor rebuilds the same filter.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of buckets.
+    pub fn num_buckets(&self) -> usize {
+        self.size
+    }
+
+    /// Bytes of the packed table.
+    pub fn size_in_bytes(&self) -> usize {
+        self.table.len() * std::mem::size_of::<u64>()
+    }
+
+    /// A lookup compares against the fingerprints stored in two buckets, 2n/size on average,
+    /// each of which matches with probability 1/(2^f - 1) since 0 is never used as a fingerprint.
+    pub fn expected_fpr(&self) -> f64 {
+        let compared = 2f64 * self.num_items as f64 / self.size as f64;
+        1f64 - (1f64 - 1f64 / ((1u64 << self.fingerprint_size) - 1) as f64).powf(compared)
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> Filter<T> for CuckooFilter<S> {
+    fn contains(&self, item: &T) -> bool {
+        self.lookup(item)
+    }
+
+    fn len(&self) -> usize {
+        CuckooFilter::<S>::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        CuckooFilter::<S>::capacity(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        CuckooFilter::<S>::size_in_bytes(self)
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        CuckooFilter::<S>::expected_fpr(self)
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> MutableFilter<T> for CuckooFilter<S> {
+    type Error = CuckooError;
+
+    fn insert(&mut self, item: &T) -> Result<(), CuckooError> {
+        CuckooFilter::<S>::insert(self, item)
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> DeletableFilter<T> for CuckooFilter<S> {
+    fn remove(&mut self, item: &T) -> bool {
+        self.delete(item)
+    }
+}
+
+
+// Names of the parameter words, in the order to_bytes writes them. serde_support uses them as field names.
+pub(crate) const PARAM_NAMES: [&str; 6] = ["expected_items", "num_buckets", "fingerprint_size", "bucket_size", "num_items", "kick_state"];
+
+// The payload is the packed table. seed0 and seed1 are derived again from the filter seed; the
+// eviction generator state is kept so that a reloaded filter makes the same evictions.
+impl<S: SerializableHasher> CuckooFilter<S> {
+    /// Serializes the filter in the format described in serialization.rs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let params: [u64; PARAM_NAMES.len()] = [
+            self.expected_items as u64,
+            self.size as u64,
+            self.fingerprint_size as u64,
+            self.bucket_size as u64,
+            self.num_items as u64,
+            self.kick_rng.state(),
+        ];
+        serialization::encode(FilterKind::Cuckoo, S::ID, self.hash_builder.state(), self.seed, &params, &words_to_bytes(&self.table))
+    }
+
+    /// Writes to_bytes() to writer.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        serialization::write_all(writer, &self.to_bytes())
+    }
+
+    /// Loads a filter written by to_bytes, checking its framing, checksum and parameters.
+    pub fn from_bytes_with_hasher(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let encoded = serialization::decode(bytes, FilterKind::Cuckoo, S::ID)?;
+        let [expected_items, size, fingerprint_size, bucket_size, num_items, kick_state] = encoded.params[..] else {
+            return Err(SerializationError::InvalidParameters("a cuckoo filter has 6 parameters"));
+        };
+        let size = to_usize(size)?;
+        let fingerprint_size = to_usize(fingerprint_size)?;
+        let bucket_size = to_usize(bucket_size)?;
+        if !size.is_power_of_two() || !(4..=32).contains(&fingerprint_size) || !matches!(bucket_size, 1 | 2 | 4 | 8) {
+            return Err(SerializationError::InvalidParameters("cuckoo filter parameters out of range"));
+        }
+        // same table length as with_params_and_hasher, checked before allocating it
+        let num_words = size.checked_mul(bucket_size * fingerprint_size).map(|bits| bits.div_ceil(64) + 1);
+        if num_words != Some(encoded.payload.len() / 8) {
+            return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
         }
+        let mut filter = Self::with_params_and_hasher(size, fingerprint_size, bucket_size, encoded.seed, S::from_state(encoded.hasher_state));
+        bytes_to_words(encoded.payload, &mut filter.table)?;
+        // delete relies on num_items counting the occupied slots
+        let occupied = (0..size).map(|i| (0..bucket_size).filter(|&j| filter.read_slot(i, j) != 0).count()).sum::<usize>();
+        if to_usize(num_items)? != occupied {
+            return Err(SerializationError::InvalidParameters("num_items does not match the occupied slots"));
+        }
+        filter.expected_items = to_usize(expected_items)?;
+        filter.num_items = occupied;
+        filter.kick_rng = SplitMix64::new(kick_state);
+        Ok(filter)
+    }
 
-        false
+    /// Reads all of reader and loads it with from_bytes_with_hasher.
+    pub fn read_from_with_hasher<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
     }
 }
 
+impl CuckooFilter {
+    /// Loads a filter built with the default hasher, see from_bytes_with_hasher.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(bytes)
+    }
+
+    /// Reads all of reader and loads it with from_bytes.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
+    }
+}
 
 // test the cuckoo filter.
 
@@ -133,23 +456,25 @@ fn compute_mean_and_variance(times: &[Duration]) -> (f64, f64) {
 
 
 
+/// Benchmarks the cuckoo filter and prints space, false positive rate and timings.
 pub fn test_cuckoo_filters() {
-    // change SIZE of the cuckoo filter according to ITEM_NUM.
-    // SIZE needs to be power of 2. load_factor is in this case is specifically designed as 0.95.
+    // SIZE is derived from ITEM_NUM: the smallest power of 2 number of buckets reaching a 0.95 load factor.
     const ITEM_NUM: i32 = 996147;
-    const SIZE:usize = 262144;
-    let mut filter = CuckooFilter::new(SIZE); // Adjust size as needed. Power of 2.
-    let bits_per_item = (filter.size*BUCKET_SIZE*FINGERPRINT_SIZE) as f64 /ITEM_NUM as f64;
+    let size = num_buckets_for_capacity(ITEM_NUM as usize, DEFAULT_BUCKET_SIZE);
+    let mut filter = CuckooFilter::new(size);
+    let bits_per_item = (filter.size_in_bytes()*8) as f64 /ITEM_NUM as f64;
     println!("Cuckoo bits/item is {:?}", bits_per_item);
     
     //insertion check
     let cuckoo_f_insertion_start_time = Instant::now();
     //load factor set to 0.95.
+    let mut failed_num = 0;
     for i in 1..=ITEM_NUM {
-        filter.insert(&i);
+        if filter.insert(&i).is_err(){failed_num+=1;}
     }
     let cuckoo_f_insertion_duration = cuckoo_f_insertion_start_time.elapsed();
     println!("Cuckoo Filter Construction Time per item for {:?} items: {:?}",ITEM_NUM,cuckoo_f_insertion_duration/ITEM_NUM as u32);
+    println!("Cuckoo Filter failed insertions: {:?}",failed_num);
 
     //membership query for inserted items
     let cuckoo_f_lookup_start_time = Instant::now();
@@ -199,11 +524,11 @@ pub fn test_cuckoo_filters() {
     let mut neg_check_times: Vec<Duration> = Vec::with_capacity(test_num);
     let mut deletion_times: Vec<Duration> = Vec::with_capacity(test_num);
     for _ in 0..test_num{
-        let mut filter = CuckooFilter::new(SIZE); // Adjust size as needed. Power of 2.
+        let mut filter = CuckooFilter::new(size);
         let cuckoo_f_insertion_start_time = Instant::now();
         //load factor set to 0.95.
         for i in 1..=ITEM_NUM {
-            filter.insert(&i);
+            filter.insert(&i).ok();
         }
         let cuckoo_f_insertion_duration = cuckoo_f_insertion_start_time.elapsed();
         construct_times.push(cuckoo_f_insertion_duration);
@@ -239,4 +564,82 @@ pub fn test_cuckoo_filters() {
 
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::tests::assert_round_trips;
+
+    fn filled() -> CuckooFilter {
+        let mut filter = CuckooFilter::with_seed(1000, 0.01, 7);
+        for i in 0..1000u32 {
+            filter.insert(&i).unwrap();
+        }
+        filter
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let filter = filled();
+        let mut loaded = assert_round_trips(&filter.to_bytes(), CuckooFilter::from_bytes, |bytes| CuckooFilter::read_from(bytes), |filter, out| filter.write_to(out));
+        assert_eq!(loaded.len(), filter.len());
+        assert!((0..2000u32).all(|i| loaded.lookup(&i) == filter.lookup(&i)));
+        // the eviction generator comes along, so later inserts place items the same way
+        let mut original = filter;
+        for i in 1000..1500u32 {
+            assert_eq!(loaded.insert(&i), original.insert(&i));
+        }
+        assert_eq!(loaded.to_bytes(), original.to_bytes());
+    }
+
+    #[test]
+    fn capacity_is_the_number_of_items_the_filter_was_sized_for() {
+        let filter = filled();
+        assert_eq!(filter.capacity(), 1000);
+        assert_eq!(filter.num_slots(), filter.num_buckets() * filter.bucket_size());
+        assert_eq!(CuckooFilter::from_bytes(&filter.to_bytes()).unwrap().capacity(), 1000);
+        let filter = CuckooFilter::with_params_and_seed(64, 12, 4, 7);
+        assert_eq!(filter.num_slots(), 256);
+        assert_eq!(filter.capacity(), (256f64 * 0.95) as usize);
+    }
 
+    #[test]
+    fn failed_insert_leaves_the_filter_unchanged() {
+        let mut filter = CuckooFilter::with_params_and_hasher(8, 8, 2, 42, DefaultBuildHasher::default());
+        let mut inserted = Vec::new();
+        let mut key = 0u64;
+        loop {
+            let bytes = filter.to_bytes();
+            match filter.insert(&key) {
+                Ok(()) => inserted.push(key),
+                Err(CuckooError::Full) => {
+                    assert_eq!(filter.to_bytes(), bytes);
+                    break;
+                }
+            }
+            key += 1;
+        }
+        assert!(inserted.len() > 8);
+        assert!(inserted.iter().all(|key| filter.lookup(key)));
+        // the filter keeps working after a failed insert
+        assert!(filter.delete(&inserted[0]));
+        assert_eq!(filter.len(), inserted.len() - 1);
+    }
+
+    #[test]
+    fn from_bytes_rejects_a_wrong_item_count() {
+        let mut filter = CuckooFilter::with_params_and_seed(64, 12, 4, 7);
+        for key in 0..100u64 {
+            filter.insert(&key).unwrap();
+        }
+        let bytes = filter.to_bytes();
+        let encoded = serialization::decode(&bytes, FilterKind::Cuckoo, DefaultBuildHasher::ID).unwrap();
+        for num_items in [0, 99, 101, u64::MAX] {
+            let mut params = encoded.params.clone();
+            params[4] = num_items;
+            let forged = serialization::encode(FilterKind::Cuckoo, DefaultBuildHasher::ID, encoded.hasher_state, encoded.seed, &params, encoded.payload);
+            assert!(matches!(CuckooFilter::from_bytes(&forged), Err(SerializationError::InvalidParameters(_))));
+        }
+        let loaded = CuckooFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.len(), 100);
+    }
+}
@@ -0,0 +1,121 @@
+//! Error types returned by the filters.
+
+use std::fmt;
+use std::io;
+
+/// Why an insert into a CuckooFilter failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CuckooError {
+    /// No free slot was found within the kick limit. The filter is left unchanged.
+    Full,
+}
+
+impl fmt::Display for CuckooError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CuckooError::Full => write!(f, "cuckoo filter is full"),
+        }
+    }
+}
+
+impl std::error::Error for CuckooError {}
+
+/// Why a static filter (XorFilter or BFFilter) could not be built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstructionError {
+    /// Peeling failed for every seed tried. With duplicates removed this is very unlikely unless
+    /// the table is undersized for the number of keys.
+    TooManyRetries {
+        /// Number of seeds tried.
+        attempts: usize,
+    },
+}
+
+impl fmt::Display for ConstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstructionError::TooManyRetries { attempts } => {
+                write!(f, "filter construction failed after {} seeds", attempts)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConstructionError {}
+
+/// Why a serialized filter could not be loaded.
+#[derive(Debug)]
+pub enum SerializationError {
+    /// Reading or writing the underlying reader or writer failed.
+    Io(io::Error),
+    /// The input ends before the header, parameters, payload or checksum it announces.
+    Truncated,
+    /// The input does not start with the format's magic bytes.
+    BadMagic,
+    /// The input was written by a format version this crate cannot read.
+    UnsupportedVersion(u16),
+    /// The bytes hold a different kind of filter than the one being loaded.
+    KindMismatch {
+        /// FilterKind of the filter being loaded.
+        expected: u8,
+        /// FilterKind stored in the bytes.
+        found: u8,
+    },
+    /// The filter was saved with a different hasher than the one it is loaded with.
+    HasherMismatch {
+        /// SerializableHasher::ID of the hasher being loaded.
+        expected: u32,
+        /// Hasher id stored in the bytes.
+        found: u32,
+    },
+    /// The stored checksum does not match the bytes: the input is corrupted.
+    ChecksumMismatch {
+        /// Checksum stored in the bytes.
+        expected: u32,
+        /// Checksum computed over the bytes.
+        found: u32,
+    },
+    /// Parameters that do not fit the filter type (e.g. a 16-bit xor filter loaded as Xor8) or
+    /// do not match the payload length.
+    InvalidParameters(&'static str),
+    /// A borrowed view cannot read the payload in place: the buffer is not aligned for the payload's
+    /// element type, or the target is big-endian.
+    Misaligned,
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializationError::Io(err) => write!(f, "i/o error: {}", err),
+            SerializationError::Truncated => write!(f, "serialized filter is truncated"),
+            SerializationError::BadMagic => write!(f, "not a serialized filter"),
+            SerializationError::UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            SerializationError::KindMismatch { expected, found } => {
+                write!(f, "expected filter kind {}, found {}", expected, found)
+            }
+            SerializationError::HasherMismatch { expected, found } => {
+                write!(f, "expected hasher id {}, found {}", expected, found)
+            }
+            SerializationError::ChecksumMismatch { expected, found } => {
+                write!(f, "checksum mismatch: stored {:#010x}, computed {:#010x}", expected, found)
+            }
+            SerializationError::InvalidParameters(reason) => write!(f, "invalid parameters: {}", reason),
+            SerializationError::Misaligned => write!(f, "payload cannot be read in place"),
+        }
+    }
+}
+
+impl std::error::Error for SerializationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SerializationError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SerializationError {
+    fn from(err: io::Error) -> Self {
+        SerializationError::Io(err)
+    }
+}
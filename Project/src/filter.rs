@@ -0,0 +1,45 @@
+//! Common interface shared by every filter in the crate, so application code can switch
+//! between Bloom, Blocked Bloom, Cuckoo, Xor and Binary Fuse filters without touching call sites.
+
+/// An approximate membership filter over items of type `T`.
+///
+/// `contains` never returns `false` for an item that was inserted (or used to build the
+/// filter), but may return `true` for items that were not.
+pub trait Filter<T: ?Sized> {
+    /// Returns `true` if `item` may be in the set, `false` if it is definitely not.
+    fn contains(&self, item: &T) -> bool;
+
+    /// Number of items currently represented by the filter.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if no item has been added to the filter.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of items the filter was sized for.
+    fn capacity(&self) -> usize;
+
+    /// Heap memory used by the filter's table, in bytes.
+    fn size_in_bytes(&self) -> usize;
+
+    /// False-positive rate expected with the filter's current load.
+    fn expected_fpr(&self) -> f64;
+}
+
+/// A filter that accepts new items after construction.
+pub trait MutableFilter<T: ?Sized>: Filter<T> {
+    /// Why an insert failed; `std::convert::Infallible` for filters that always accept items.
+    type Error;
+
+    /// Adds `item` to the filter. On error the filter is left unchanged.
+    fn insert(&mut self, item: &T) -> Result<(), Self::Error>;
+}
+
+/// A filter that can forget items which were previously inserted.
+pub trait DeletableFilter<T: ?Sized>: MutableFilter<T> {
+    /// Removes one occurrence of `item`. Returns `false` if it was not found.
+    ///
+    /// Removing an item that was never inserted may delete a colliding item instead.
+    fn remove(&mut self, item: &T) -> bool;
+}
@@ -0,0 +1,87 @@
+//! Fingerprint types usable by the static filters (XorFilter and BFFilter). Wider fingerprints cost
+//! more bits per key but lower the false positive rate to 1 / 2^BITS.
+
+use std::ops::BitXor;
+use crate::serialization;
+
+/// Fingerprint type of the static filters, implemented for u8, u16 and u32.
+pub trait Fingerprint: Copy + Eq + Default + BitXor<Output = Self> {
+    /// Width of the fingerprint in bits.
+    const BITS: u32;
+
+    /// Takes the top BITS bits of a 64-bit key hash.
+    fn from_hash(hash: u64) -> Self;
+
+    /// Little-endian encoding on BITS / 8 bytes, used by the serialized format.
+    fn write_le(self, out: &mut Vec<u8>);
+
+    /// Reads the first BITS / 8 bytes of bytes.
+    fn read_le(bytes: &[u8]) -> Self;
+
+    /// Views a little-endian payload in place, as done by the borrowed filter views. None if bytes
+    /// is not aligned for Self, not a whole number of fingerprints, or the target is big-endian.
+    fn cast_slice(bytes: &[u8]) -> Option<&[Self]>;
+}
+
+impl Fingerprint for u8 {
+    const BITS: u32 = 8;
+
+    fn from_hash(hash: u64) -> Self {
+        (hash >> (64 - Self::BITS)) as u8
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        u8::from_le_bytes([bytes[0]])
+    }
+
+    fn cast_slice(bytes: &[u8]) -> Option<&[Self]> {
+        // every bit pattern is a valid u8
+        unsafe { serialization::cast_slice(bytes) }
+    }
+}
+
+impl Fingerprint for u16 {
+    const BITS: u32 = 16;
+
+    fn from_hash(hash: u64) -> Self {
+        (hash >> (64 - Self::BITS)) as u16
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        u16::from_le_bytes([bytes[0], bytes[1]])
+    }
+
+    fn cast_slice(bytes: &[u8]) -> Option<&[Self]> {
+        // every bit pattern is a valid u16
+        unsafe { serialization::cast_slice(bytes) }
+    }
+}
+
+impl Fingerprint for u32 {
+    const BITS: u32 = 32;
+
+    fn from_hash(hash: u64) -> Self {
+        (hash >> (64 - Self::BITS)) as u32
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn cast_slice(bytes: &[u8]) -> Option<&[Self]> {
+        // every bit pattern is a valid u32
+        unsafe { serialization::cast_slice(bytes) }
+    }
+}
@@ -0,0 +1,127 @@
+//! Hashing used by the filters. Every filter is generic over a std::hash::BuildHasher and defaults to
+//! BuildFastHasher below, a fast non-cryptographic hash. Use std::collections::hash_map::RandomState
+//! instead when keys may be chosen by an attacker and DoS resistance matters more than speed.
+
+use std::hash::{BuildHasher, Hasher};
+
+/// Hasher builder used by the filters when none is given.
+pub type DefaultBuildHasher = BuildFastHasher;
+
+const MULTIPLIER: u64 = 0x9E37_79B9_7F4A_7C15;
+const INIT: u64 = 0x243F_6A88_85A3_08D3;
+
+// Low and high halves of the 128-bit product xored together.
+#[inline]
+fn folded_multiply(a: u64, b: u64) -> u64 {
+    let full = (a as u128) * (b as u128);
+    (full as u64) ^ ((full >> 64) as u64)
+}
+
+/// Finalizer of MurmurHash3: every input bit affects every output bit.
+#[inline]
+pub fn fmix64(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^= x >> 33;
+    x
+}
+
+/// Streaming 64-bit hasher: each word is folded into the state with one 64x64->128 bit multiply.
+#[derive(Clone, Copy, Debug)]
+pub struct FastHasher {
+    state: u64,
+}
+
+impl FastHasher {
+    /// Hasher starting from the given seed.
+    pub fn with_seed(seed: u64) -> Self {
+        FastHasher { state: INIT ^ seed }
+    }
+
+    #[inline]
+    fn mix(&mut self, word: u64) {
+        self.state = folded_multiply(self.state ^ word, MULTIPLIER);
+    }
+}
+
+impl Default for FastHasher {
+    fn default() -> Self {
+        FastHasher::with_seed(0)
+    }
+}
+
+impl Hasher for FastHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(chunk);
+            self.mix(u64::from_le_bytes(word));
+        }
+        let rest = chunks.remainder();
+        if !rest.is_empty() {
+            // at most 7 bytes, so the top byte is free to record how many there were
+            let mut word = [0u8; 8];
+            word[..rest.len()].copy_from_slice(rest);
+            self.mix(u64::from_le_bytes(word) | (rest.len() as u64) << 56);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.mix(i as u64);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.mix(i as u64);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.mix(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.mix(i);
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.mix(i as u64);
+        self.mix((i >> 64) as u64);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.mix(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        fmix64(self.state)
+    }
+}
+
+/// Builds FastHashers. Unlike RandomState it is not randomized per process, so the same key always
+/// hashes to the same value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BuildFastHasher {
+    seed: u64,
+}
+
+impl BuildFastHasher {
+    /// Builder of FastHashers started from seed. Different seeds give unrelated hash functions.
+    pub fn with_seed(seed: u64) -> Self {
+        BuildFastHasher { seed }
+    }
+
+    /// Seed given to with_seed.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl BuildHasher for BuildFastHasher {
+    type Hasher = FastHasher;
+
+    fn build_hasher(&self) -> FastHasher {
+        FastHasher::with_seed(self.seed)
+    }
+}
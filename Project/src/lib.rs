@@ -0,0 +1,49 @@
+//! A rust implementation of Bloom Filter, Counting Bloom Filter, Scalable Bloom Filter, Blocked Bloom Filter, Cuckoo Filter, XOR Filter and Binary Fuse Filter.
+
+#![warn(missing_docs)]
+
+/// Errors returned by the filters.
+pub mod error;
+/// Traits shared by every filter.
+pub mod filter;
+/// Fingerprint types of the static filters.
+pub mod fingerprint;
+/// Hashing of items.
+pub mod hasher;
+/// Reduction of hashes to table indices.
+pub mod reduce;
+/// Seeding of the filters.
+pub mod seed;
+/// Binary format shared by every filter.
+pub mod serialization;
+#[cfg(feature = "serde")]
+mod serde_support;
+/// Bloom filter.
+pub mod bloom_filter;
+/// Counting bloom filter, a bloom filter that supports removals.
+pub mod counting_bloom_filter;
+/// Scalable bloom filter, a bloom filter that grows with the number of items.
+pub mod scalable_bloom_filter;
+/// Cache-friendly blocked bloom filter.
+pub mod blocked_bloom_filter;
+/// Cuckoo filter.
+pub mod cuckoo_filter;
+/// Xor filter.
+pub mod xor_filter;
+/// Binary fuse filter.
+pub mod binary_fuse_filter;
+
+pub use crate::error::{ConstructionError, CuckooError, SerializationError};
+pub use crate::filter::{DeletableFilter, Filter, MutableFilter};
+pub use crate::fingerprint::Fingerprint;
+pub use crate::hasher::{BuildFastHasher, DefaultBuildHasher, FastHasher};
+pub use crate::serialization::{FilterKind, SerializableHasher};
+pub use crate::bloom_filter::{BloomFilter, BloomFilterRef, IndexingMode};
+pub use crate::counting_bloom_filter::CountingBloomFilter;
+pub use crate::scalable_bloom_filter::ScalableBloomFilter;
+pub use crate::blocked_bloom_filter::BlockedBloomFilter;
+pub use crate::cuckoo_filter::CuckooFilter;
+pub use crate::xor_filter::{Xor16, Xor32, Xor8, XorFilter, XorFilterRef};
+pub use crate::binary_fuse_filter::{
+    BFFilter, BFFilterRef, BinaryFuse16, BinaryFuse32, BinaryFuse4Wise16, BinaryFuse4Wise32, BinaryFuse4Wise8, BinaryFuse8,
+};
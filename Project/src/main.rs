@@ -1,19 +1,17 @@
-use crate::cuckoo_filter::test_cuckoo_filters;
-use crate::bloom_filter::test_bloom_filters;
-use crate::blocked_bloom_filter::test_blocked_bloom_filters;
-use crate::xor_filter::test_xor_filters;
-use crate::binary_fuse_filter::test_bff;
-
-mod bloom_filter;
-mod blocked_bloom_filter;
-mod cuckoo_filter;
-mod xor_filter;
-mod binary_fuse_filter;
+use thesis_project::cuckoo_filter::test_cuckoo_filters;
+use thesis_project::bloom_filter::test_bloom_filters;
+use thesis_project::counting_bloom_filter::test_counting_bloom_filters;
+use thesis_project::scalable_bloom_filter::test_scalable_bloom_filters;
+use thesis_project::blocked_bloom_filter::test_blocked_bloom_filters;
+use thesis_project::xor_filter::test_xor_filters;
+use thesis_project::binary_fuse_filter::test_bff;
 
 
 
 fn main() {
     test_bloom_filters();
+    test_counting_bloom_filters();
+    test_scalable_bloom_filters();
     test_blocked_bloom_filters();
     test_cuckoo_filters();
     test_xor_filters();
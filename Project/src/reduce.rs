@@ -0,0 +1,9 @@
+//! Index reduction shared by every filter: maps a 64-bit hash to a slot in [0, n).
+
+/// Lemire's fastrange: the high 64 bits of x * n. It costs one multiply instead of a division and
+/// uses all 64 bits of x, so tables with more than 2^32 slots are still covered uniformly. It keeps
+/// the top bits of x, which are the well mixed ones after a multiply-shift.
+#[inline]
+pub fn fastrange(x: u64, n: usize) -> usize {
+    ((x as u128 * n as u128) >> 64) as usize
+}
@@ -0,0 +1,481 @@
+use std::hash::{BuildHasher, Hash};
+use std::convert::Infallible;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+use crate::bloom_filter::{self, BloomFilter};
+use crate::error::SerializationError;
+use crate::filter::{Filter, MutableFilter};
+use crate::hasher::DefaultBuildHasher;
+use crate::seed::{random_seed, SplitMix64};
+use crate::serialization::{self, to_usize, FilterKind, SerializableHasher};
+
+/// Each stage holds growth_factor times as many items as the one before it.
+pub const DEFAULT_GROWTH_FACTOR: usize = 2;
+/// Each stage has tightening_ratio times the false positive rate of the one before it.
+pub const DEFAULT_TIGHTENING_RATIO: f64 = 0.9;
+
+/// Scalable bloom filter (Almeida et al., 2007) for when the number of items is not known in advance.
+/// Items go into the newest of a chain of BloomFilter stages; once it holds as many items as it was
+/// sized for, a new stage is added with growth_factor times the capacity and tightening_ratio times
+/// the false positive rate. Stage i gets p (1 - r) r^i, so however many stages are added the
+/// compound false positive rate stays below the sum p (1 - r) (1 + r + r^2 + ...) = p.
+pub struct ScalableBloomFilter<S = DefaultBuildHasher> {
+    stages: Vec<BloomFilter<S>>,
+    initial_capacity: usize,
+    false_positive_rate: f64,
+    growth_factor: usize,
+    tightening_ratio: f64,
+    seed: u64,// stage seeds are all derived from it
+    hash_builder: S,
+}
+
+// Seed of stage i: output i (counting from 0) of a SplitMix64 seeded with the filter seed.
+fn stage_seed(seed: u64, stage: usize) -> u64 {
+    let mut rng = SplitMix64::new(seed);
+    for _ in 0..stage {
+        rng.next_u64();
+    }
+    rng.next_u64()
+}
+
+impl ScalableBloomFilter {
+    /// Initialize a new ScalableBloomFilter with the default false positive rate of 0.0074
+    pub fn new(initial_capacity: usize) -> ScalableBloomFilter {
+        Self::with_fpr(initial_capacity, bloom_filter::DEFAULT_FALSE_POSITIVE_RATE)
+    }
+
+    /// Initialize a new ScalableBloomFilter whose compound false positive rate stays below false_positive_rate
+    pub fn with_fpr(initial_capacity: usize, false_positive_rate: f64) -> ScalableBloomFilter {
+        Self::with_params(initial_capacity, false_positive_rate, DEFAULT_GROWTH_FACTOR, DEFAULT_TIGHTENING_RATIO)
+    }
+
+    /// Initialize a new ScalableBloomFilter with explicit growth factor and tightening ratio
+    pub fn with_params(initial_capacity: usize, false_positive_rate: f64, growth_factor: usize, tightening_ratio: f64) -> ScalableBloomFilter {
+        Self::with_params_and_seed(initial_capacity, false_positive_rate, growth_factor, tightening_ratio, random_seed())
+    }
+
+    /// Same as with_params with a fixed seed.
+    pub fn with_params_and_seed(initial_capacity: usize, false_positive_rate: f64, growth_factor: usize, tightening_ratio: f64, seed: u64) -> ScalableBloomFilter {
+        Self::with_params_and_hasher(initial_capacity, false_positive_rate, growth_factor, tightening_ratio, seed, DefaultBuildHasher::default())
+    }
+
+    /// Initialize a new ScalableBloomFilter with a fixed seed. The same seed and items always give the same stages.
+    pub fn with_seed(initial_capacity: usize, false_positive_rate: f64, seed: u64) -> ScalableBloomFilter {
+        Self::with_params_and_hasher(initial_capacity, false_positive_rate, DEFAULT_GROWTH_FACTOR, DEFAULT_TIGHTENING_RATIO, seed, DefaultBuildHasher::default())
+    }
+}
+
+impl<S: BuildHasher + Clone> ScalableBloomFilter<S> {
+    /// Initialize a new ScalableBloomFilter, hashing items with hash_builder. Every stage shares it.
+    pub fn with_params_and_hasher(initial_capacity: usize, false_positive_rate: f64, growth_factor: usize, tightening_ratio: f64, seed: u64, hash_builder: S) -> Self {
+        assert!(initial_capacity > 0, "a scalable bloom filter needs an initial capacity of at least one item");
+        assert!(false_positive_rate > 0f64 && false_positive_rate < 1f64, "false positive rate must be in (0, 1)");
+        assert!(growth_factor > 0, "growth factor must be at least 1");
+        assert!(tightening_ratio > 0f64 && tightening_ratio < 1f64, "tightening ratio must be in (0, 1)");
+        let mut filter = ScalableBloomFilter {
+            stages: Vec::new(),
+            initial_capacity,
+            false_positive_rate,
+            growth_factor,
+            tightening_ratio,
+            seed,
+            hash_builder,
+        };
+        filter.add_stage();
+        filter
+    }
+
+    fn stage_capacity(&self, stage: usize) -> usize {
+        (0..stage).fold(self.initial_capacity, |capacity, _| capacity.saturating_mul(self.growth_factor))
+    }
+
+    fn stage_fpr(&self, stage: usize) -> f64 {
+        self.false_positive_rate * (1f64 - self.tightening_ratio) * self.tightening_ratio.powi(stage as i32)
+    }
+
+    fn add_stage(&mut self) {
+        let stage = self.stages.len();
+        let capacity = self.stage_capacity(stage);
+        let fpr = self.stage_fpr(stage);
+        self.stages.push(BloomFilter::with_fpr_and_hasher(capacity, fpr, stage_seed(self.seed, stage), self.hash_builder.clone()));
+    }
+
+    /// Add an item to the filter, adding a stage first if the newest one is full
+    pub fn add<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.insert_hash(self.hash_builder.hash_one(item));
+    }
+
+    /// Check if an item might be in any stage
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.contains_hash(self.hash_builder.hash_one(item))
+    }
+
+    /// Same as add for a caller that already hashed the item with this filter's hasher.
+    pub fn insert_hash(&mut self, hash: u64) {
+        let newest = self.stages.last().expect("a scalable bloom filter has at least one stage");
+        if newest.len() >= newest.capacity() {
+            self.add_stage();
+        }
+        self.stages.last_mut().expect("a scalable bloom filter has at least one stage").insert_hash(hash);
+    }
+
+    /// Same as contains for a caller that already hashed the item with this filter's hasher.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        // newest first: it is the largest and holds the most recent items
+        self.stages.iter().rev().any(|stage| stage.contains_hash(hash))
+    }
+
+    /// Number of add calls so far (duplicates are counted again).
+    pub fn len(&self) -> usize {
+        self.stages.iter().map(|stage| stage.len()).sum()
+    }
+
+    /// True if nothing was added yet.
+    pub fn is_empty(&self) -> bool {
+        self.stages.iter().all(|stage| stage.is_empty())
+    }
+
+    /// Items the current stages were sized for. Adding more makes a new stage instead of raising the
+    /// false positive rate.
+    pub fn capacity(&self) -> usize {
+        self.stages.iter().fold(0usize, |total, stage| total.saturating_add(stage.capacity()))
+    }
+
+    /// Number of BloomFilter stages so far.
+    pub fn num_stages(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// The stages, oldest first.
+    pub fn stages(&self) -> &[BloomFilter<S>] {
+        &self.stages
+    }
+
+    /// Capacity of the first stage.
+    pub fn initial_capacity(&self) -> usize {
+        self.initial_capacity
+    }
+
+    /// Ratio between the capacities of consecutive stages.
+    pub fn growth_factor(&self) -> usize {
+        self.growth_factor
+    }
+
+    /// Ratio between the false positive rates of consecutive stages.
+    pub fn tightening_ratio(&self) -> f64 {
+        self.tightening_ratio
+    }
+
+    /// Seed the filter was built with; passing it back to a constructor rebuilds the same filter.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Bytes of the bit vectors of all stages.
+    pub fn size_in_bytes(&self) -> usize {
+        self.stages.iter().map(|stage| stage.size_in_bytes()).sum()
+    }
+
+    /// Upper bound on the compound false positive rate once every current stage is full: the sum of
+    /// the stage targets, always below the false positive rate the filter was built with.
+    pub fn fpr_bound(&self) -> f64 {
+        (0..self.stages.len()).map(|stage| self.stage_fpr(stage)).sum()
+    }
+
+    /// 1 - prod(1 - p_i) over the stages at their current load.
+    pub fn expected_fpr(&self) -> f64 {
+        1f64 - self.stages.iter().map(|stage| 1f64 - stage.expected_fpr()).product::<f64>()
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher + Clone> Filter<T> for ScalableBloomFilter<S> {
+    fn contains(&self, item: &T) -> bool {
+        ScalableBloomFilter::<S>::contains(self, item)
+    }
+
+    fn len(&self) -> usize {
+        ScalableBloomFilter::<S>::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        ScalableBloomFilter::<S>::capacity(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        ScalableBloomFilter::<S>::size_in_bytes(self)
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        ScalableBloomFilter::<S>::expected_fpr(self)
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher + Clone> MutableFilter<T> for ScalableBloomFilter<S> {
+    type Error = Infallible;
+
+    fn insert(&mut self, item: &T) -> Result<(), Infallible> {
+        self.add(item);
+        Ok(())
+    }
+}
+
+// Names of the parameter words, in the order to_bytes writes them. serde_support uses them as field names.
+pub(crate) const PARAM_NAMES: [&str; 5] = ["initial_capacity", "false_positive_rate", "growth_factor", "tightening_ratio", "num_stages"];
+
+// The two rates are saved as f64 bits. The payload is each stage as written by BloomFilter::to_bytes,
+// preceded by its length as a u64.
+impl<S: SerializableHasher + Clone> ScalableBloomFilter<S> {
+    /// Serializes the filter in the format described in serialization.rs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let params: [u64; PARAM_NAMES.len()] = [
+            self.initial_capacity as u64,
+            self.false_positive_rate.to_bits(),
+            self.growth_factor as u64,
+            self.tightening_ratio.to_bits(),
+            self.stages.len() as u64,
+        ];
+        let mut payload = Vec::new();
+        for stage in &self.stages {
+            let bytes = stage.to_bytes();
+            payload.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            payload.extend_from_slice(&bytes);
+        }
+        serialization::encode(FilterKind::ScalableBloom, S::ID, self.hash_builder.state(), self.seed, &params, &payload)
+    }
+
+    /// Writes to_bytes() to writer.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        serialization::write_all(writer, &self.to_bytes())
+    }
+
+    /// Loads a filter written by to_bytes, checking its framing, checksum and parameters, and those of every stage.
+    pub fn from_bytes_with_hasher(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let encoded = serialization::decode(bytes, FilterKind::ScalableBloom, S::ID)?;
+        let [initial_capacity, false_positive_rate, growth_factor, tightening_ratio, num_stages] = encoded.params[..] else {
+            return Err(SerializationError::InvalidParameters("a scalable bloom filter has 5 parameters"));
+        };
+        let initial_capacity = to_usize(initial_capacity)?;
+        let false_positive_rate = f64::from_bits(false_positive_rate);
+        let growth_factor = to_usize(growth_factor)?;
+        let tightening_ratio = f64::from_bits(tightening_ratio);
+        let num_stages = to_usize(num_stages)?;
+        if initial_capacity == 0
+            || !(false_positive_rate > 0f64 && false_positive_rate < 1f64)
+            || growth_factor == 0
+            || !(tightening_ratio > 0f64 && tightening_ratio < 1f64)
+            || num_stages == 0
+        {
+            return Err(SerializationError::InvalidParameters("scalable bloom filter parameters out of range"));
+        }
+        let mut stages = Vec::new();
+        let mut rest = encoded.payload;
+        for _ in 0..num_stages {
+            if rest.len() < 8 {
+                return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
+            }
+            let (len, tail) = rest.split_at(8);
+            let len = to_usize(u64::from_le_bytes(len.try_into().expect("8 bytes")))?;
+            if tail.len() < len {
+                return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
+            }
+            let (stage, tail) = tail.split_at(len);
+            // loading with S already rejects a stage saved with another hasher id
+            let stage = BloomFilter::<S>::from_bytes_with_hasher(stage)?;
+            if stage.hasher_state() != encoded.hasher_state {
+                return Err(SerializationError::InvalidParameters("stage hasher state differs from the filter's"));
+            }
+            stages.push(stage);
+            rest = tail;
+        }
+        if !rest.is_empty() {
+            return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
+        }
+        Ok(ScalableBloomFilter {
+            stages,
+            initial_capacity,
+            false_positive_rate,
+            growth_factor,
+            tightening_ratio,
+            seed: encoded.seed,
+            hash_builder: S::from_state(encoded.hasher_state),
+        })
+    }
+
+    /// Reads all of reader and loads it with from_bytes_with_hasher.
+    pub fn read_from_with_hasher<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
+    }
+}
+
+impl ScalableBloomFilter {
+    /// Loads a filter built with the default hasher, see from_bytes_with_hasher.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(bytes)
+    }
+
+    /// Reads all of reader and loads it with from_bytes.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
+    }
+}
+
+// The test only works for adding natural numbers from 1 to expected_items for simplicity.
+// The test logic needs to be changed if user wants to check for adding different kinds of numbers.
+
+fn compute_mean_and_variance(times: &[Duration]) -> (f64, f64) {
+    let times_in_secs: Vec<f64> = times.iter()
+        .map(|d| d.as_secs_f64())
+        .collect();
+
+    let mean = times_in_secs.iter().sum::<f64>() / times_in_secs.len() as f64;
+
+    let variance = times_in_secs.iter()
+        .map(|time| (time - mean).powi(2))
+        .sum::<f64>() / times_in_secs.len() as f64;
+
+    (mean, variance)
+}
+
+// Starts far below expected_items, so the filter has to grow to hold them all.
+fn test_scalable_bloom_f_with_specified_num_of_items(expected_items: usize, initial_capacity: usize){
+    //carry out a single test
+    let mut filter = ScalableBloomFilter::new(initial_capacity);
+    let scalable_bloom_f_insertion_start_time = Instant::now();
+    for item in 1..=expected_items{
+        filter.add(&item);
+    }//insert items
+    let scalable_bloom_f_insertion_duration = scalable_bloom_f_insertion_start_time.elapsed();
+    let bits_per_item=(filter.size_in_bytes()*8) as f64/expected_items as f64;
+    println!("Scalable Bloom filter space usage: bit/item is {:?} with {:?} stages from an initial capacity of {:?}", bits_per_item, filter.num_stages(), initial_capacity);
+    println!("Scalable Bloom Filter Construction Time per item for {:?} items: {:?}",expected_items,scalable_bloom_f_insertion_duration/expected_items as u32);
+
+    let mut scalable_bloom_f_false_positive_num=0;
+    let scalable_bloom_f_neg_query_start_time = Instant::now();
+    for item in expected_items+1..=expected_items+expected_items{
+        if filter.contains(&item){scalable_bloom_f_false_positive_num+=1;}
+    }
+    let scalable_bloom_f_neg_query_duration = scalable_bloom_f_neg_query_start_time.elapsed();
+    let scalable_bloom_fpr= scalable_bloom_f_false_positive_num as f64/expected_items as f64;
+    println!("Scalable Bloom Filter False Positive Rate is ({:?} items) : {:?}, bound {:.6}",expected_items,scalable_bloom_fpr,filter.fpr_bound());
+    println!("Scalable Bloom Filter query Duration per item for {:?} neg items: {:?}",expected_items,scalable_bloom_f_neg_query_duration/expected_items as u32);
+    let mut scalable_bloom_f_true_positive_num=0;
+    let scalable_bloom_f_pos_query_start_time = Instant::now();
+    for item in 1..=expected_items{
+        if filter.contains(&item){scalable_bloom_f_true_positive_num+=1;}
+    }
+    let scalable_bloom_f_pos_query_duration = scalable_bloom_f_pos_query_start_time.elapsed();
+    let scalable_bloom_tpr= scalable_bloom_f_true_positive_num as f64/expected_items as f64;
+    println!("Scalable Bloom Filter True Positive Rate is ({:?} items) : {:?}",expected_items,scalable_bloom_tpr);
+    println!("Scalable Bloom Filter query Duration per item for {:?} pos items: {:?}",expected_items,scalable_bloom_f_pos_query_duration/expected_items as u32);
+
+    //carry out several tests for benchmark
+    let test_num = 20;
+    let mut construct_times: Vec<Duration> = Vec::with_capacity(test_num);
+    let mut pos_check_times: Vec<Duration> = Vec::with_capacity(test_num);
+    let mut neg_check_times: Vec<Duration> = Vec::with_capacity(test_num);
+
+    for _ in 0..test_num{
+
+        let mut filter = ScalableBloomFilter::new(initial_capacity);
+        let scalable_bloom_f_insertion_start_time = Instant::now();
+        for item in 1..=expected_items{
+            filter.add(&item);
+        }//insert items
+        let scalable_bloom_f_insertion_duration = scalable_bloom_f_insertion_start_time.elapsed();
+        construct_times.push(scalable_bloom_f_insertion_duration);
+
+        let scalable_bloom_f_neg_query_start_time = Instant::now();
+        for item in expected_items+1..=expected_items+expected_items{
+            filter.contains(&item);
+        }
+        let scalable_bloom_f_neg_query_duration = scalable_bloom_f_neg_query_start_time.elapsed();
+        neg_check_times.push(scalable_bloom_f_neg_query_duration);
+
+        let scalable_bloom_f_pos_query_start_time = Instant::now();
+        for item in 1..=expected_items{
+            filter.contains(&item);
+        }
+        let scalable_bloom_f_pos_query_duration = scalable_bloom_f_pos_query_start_time.elapsed();
+        pos_check_times.push(scalable_bloom_f_pos_query_duration);
+    }
+    let (construct_mean, construct_variance) = compute_mean_and_variance(&construct_times);
+    let (neg_check_mean, neg_check_variance) = compute_mean_and_variance(&neg_check_times);
+    let (pos_check_mean, pos_check_variance) = compute_mean_and_variance(&pos_check_times);
+
+    println!("SBF: Construction for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", expected_items, construct_mean, construct_variance);
+    println!("SBF: Negative Check for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", expected_items, neg_check_mean, neg_check_variance);
+    println!("SBF: Positive Check for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", expected_items, pos_check_mean, pos_check_variance);
+}
+
+/// Benchmarks the scalable bloom filter, grown from a small initial capacity, and prints space, false positive rate and timings.
+pub fn test_scalable_bloom_filters(){
+    test_scalable_bloom_f_with_specified_num_of_items(996147, 1000);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::tests::assert_round_trips;
+
+    fn filled() -> ScalableBloomFilter {
+        let mut filter = ScalableBloomFilter::with_params_and_seed(100, 0.01, 2, 0.5, 7);
+        for i in 0..1000u32 {
+            filter.add(&i);
+        }
+        filter
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let filter = filled();
+        assert!(filter.num_stages() > 1);
+        let mut loaded = assert_round_trips(&filter.to_bytes(), ScalableBloomFilter::from_bytes, |bytes| ScalableBloomFilter::read_from(bytes), |filter, out| filter.write_to(out));
+        assert_eq!(loaded.num_stages(), filter.num_stages());
+        assert!((0..2000u32).all(|i| loaded.contains(&i) == filter.contains(&i)));
+        // stages added after loading follow the same seeds
+        let mut grown = filter;
+        for i in 1000..3000u32 {
+            grown.add(&i);
+            loaded.add(&i);
+        }
+        assert_eq!(loaded.to_bytes(), grown.to_bytes());
+    }
+
+    #[test]
+    fn stages_grow_and_tighten_as_configured() {
+        let filter = filled();
+        assert_eq!(filter.num_stages(), 4);
+        for (i, stage) in filter.stages().iter().enumerate() {
+            let capacity = 100 * 2usize.pow(i as u32);
+            let fpr = 0.01 * 0.5 * 0.5f64.powi(i as i32);
+            assert_eq!(stage.capacity(), capacity);
+            assert_eq!(stage.num_bits(), bloom_filter::optimal_num_bits(capacity, fpr));
+        }
+        assert_eq!(filter.capacity(), 1500);
+        assert!((0..1000u32).all(|i| filter.contains(&i)));
+        assert!(filter.fpr_bound() < 0.01);
+    }
+
+    // Re-encodes the first stage with the given hasher id and state, keeping the outer header.
+    fn with_first_stage_hasher(bytes: &[u8], hasher_id: u32, hasher_state: u64) -> Vec<u8> {
+        let outer = serialization::decode(bytes, FilterKind::ScalableBloom, DefaultBuildHasher::ID).unwrap();
+        let len = u64::from_le_bytes(outer.payload[..8].try_into().unwrap()) as usize;
+        let stage = serialization::decode(&outer.payload[8..8 + len], FilterKind::Bloom, DefaultBuildHasher::ID).unwrap();
+        let forged = serialization::encode(FilterKind::Bloom, hasher_id, hasher_state, stage.seed, &stage.params, stage.payload);
+        let mut payload = (forged.len() as u64).to_le_bytes().to_vec();
+        payload.extend_from_slice(&forged);
+        payload.extend_from_slice(&outer.payload[8 + len..]);
+        serialization::encode(FilterKind::ScalableBloom, DefaultBuildHasher::ID, outer.hasher_state, outer.seed, &outer.params, &payload)
+    }
+
+    #[test]
+    fn rejects_stages_saved_with_another_hasher() {
+        let bytes = filled().to_bytes();
+        let state = serialization::decode(&bytes, FilterKind::ScalableBloom, DefaultBuildHasher::ID).unwrap().hasher_state;
+        assert!(ScalableBloomFilter::from_bytes(&with_first_stage_hasher(&bytes, DefaultBuildHasher::ID, state)).is_ok());
+        let other_state = with_first_stage_hasher(&bytes, DefaultBuildHasher::ID, state ^ 1);
+        assert!(matches!(ScalableBloomFilter::from_bytes(&other_state), Err(SerializationError::InvalidParameters(_))));
+        let other_id = with_first_stage_hasher(&bytes, DefaultBuildHasher::ID + 1, state);
+        assert!(matches!(ScalableBloomFilter::from_bytes(&other_id), Err(SerializationError::HasherMismatch { .. })));
+    }
+}
@@ -0,0 +1,42 @@
+//! Seeding shared by every filter. A filter keeps the single u64 seed it was built with and derives
+//! all of its hash seeds (and, for the cuckoo filter, its eviction choices) from it, so the same
+//! keys, seed and parameters always give a byte-identical filter.
+
+/// Seed for constructors that are not given one. Reading it back with the filter's seed() is
+/// enough to rebuild the same filter later.
+pub fn random_seed() -> u64 {
+    rand::random::<u64>()
+}
+
+/// SplitMix64, the generator used to seed xoshiro: one add and a 64-bit finalizer per output,
+/// and every seed, 0 included, gives a full-period sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    /// Generator starting from seed.
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    /// SplitMix64::new(rng.state()) continues where rng is.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Next output of the generator.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Odd values only, as needed by the multiply-shift hashes.
+    pub fn next_odd(&mut self) -> u64 {
+        self.next_u64() | 1
+    }
+}
@@ -0,0 +1,555 @@
+//! Serialize and Deserialize for every filter, enabled by the serde feature. A filter is written as
+//! a struct with the header fields of serialization.rs by name (kind, hasher id and state, seed and
+//! the filter's own parameters) followed by its table as a byte string. Loading puts the fields back
+//! into the binary format and goes through from_bytes_with_hasher, so the same checks apply.
+
+use std::fmt;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Unexpected, Visitor};
+use serde::ser::{self, SerializeStruct};
+use serde::{Deserialize, Serialize, Serializer};
+use crate::binary_fuse_filter::{self, BFFilter};
+use crate::blocked_bloom_filter::{self, BlockedBloomFilter};
+use crate::bloom_filter::{self, BloomFilter};
+use crate::counting_bloom_filter::{self, CountingBloomFilter};
+use crate::scalable_bloom_filter::{self, ScalableBloomFilter};
+use crate::cuckoo_filter::{self, CuckooFilter};
+use crate::error::SerializationError;
+use crate::fingerprint::Fingerprint;
+use crate::serialization::{self, FilterKind, SerializableHasher};
+use crate::xor_filter::{self, XorFilter};
+
+// Field names of one filter type. Every struct starts with kind, hasher_id, hasher_state and seed,
+// then names the filter's parameter words after the PARAM_NAMES of its module, and ends with payload.
+struct Layout {
+    kind: FilterKind,
+    // value of the kind field
+    kind_name: &'static str,
+    // name of the serialized struct
+    name: &'static str,
+    fields: &'static [&'static str],
+    // parameters stored as f64 bits, counted from the first parameter
+    floats: &'static [usize],
+}
+
+const HEADER_FIELDS: [&str; 4] = ["kind", "hasher_id", "hasher_state", "seed"];
+const NUM_HEADER_FIELDS: usize = HEADER_FIELDS.len();
+
+impl Layout {
+    fn num_params(&self) -> usize {
+        self.fields.len() - NUM_HEADER_FIELDS - 1
+    }
+}
+
+// The header fields, params and payload. N is checked when the layouts below are evaluated, so a
+// wrong count fails the build.
+const fn fields<const N: usize>(params: &[&'static str]) -> [&'static str; N] {
+    assert!(N == NUM_HEADER_FIELDS + params.len() + 1, "field count differs from the parameter names");
+    let mut fields = ["payload"; N];
+    let mut i = 0;
+    while i < N - 1 {
+        fields[i] = if i < NUM_HEADER_FIELDS { HEADER_FIELDS[i] } else { params[i - NUM_HEADER_FIELDS] };
+        i += 1;
+    }
+    fields
+}
+
+static BLOOM: Layout = Layout {
+    kind: FilterKind::Bloom,
+    kind_name: "bloom",
+    name: "BloomFilter",
+    fields: &fields::<10>(&bloom_filter::PARAM_NAMES),
+    floats: &[],
+};
+
+static COUNTING_BLOOM: Layout = Layout {
+    kind: FilterKind::CountingBloom,
+    kind_name: "counting_bloom",
+    name: "CountingBloomFilter",
+    fields: &fields::<12>(&counting_bloom_filter::PARAM_NAMES),
+    floats: &[],
+};
+
+static SCALABLE_BLOOM: Layout = Layout {
+    kind: FilterKind::ScalableBloom,
+    kind_name: "scalable_bloom",
+    name: "ScalableBloomFilter",
+    fields: &fields::<10>(&scalable_bloom_filter::PARAM_NAMES),
+    floats: &[1, 3],
+};
+
+static BLOCKED_BLOOM: Layout = Layout {
+    kind: FilterKind::BlockedBloom,
+    kind_name: "blocked_bloom",
+    name: "BlockedBloomFilter",
+    fields: &fields::<10>(&blocked_bloom_filter::PARAM_NAMES),
+    floats: &[],
+};
+
+static CUCKOO: Layout = Layout {
+    kind: FilterKind::Cuckoo,
+    kind_name: "cuckoo",
+    name: "CuckooFilter",
+    fields: &fields::<11>(&cuckoo_filter::PARAM_NAMES),
+    floats: &[],
+};
+
+static XOR: Layout = Layout {
+    kind: FilterKind::Xor,
+    kind_name: "xor",
+    name: "XorFilter",
+    fields: &fields::<11>(&xor_filter::PARAM_NAMES),
+    floats: &[],
+};
+
+static BINARY_FUSE_3: Layout = Layout {
+    kind: FilterKind::BinaryFuse,
+    kind_name: "binary_fuse",
+    name: "BFFilter",
+    fields: &fields::<13>(binary_fuse_filter::PARAM_NAMES.split_at(5 + 3).0),
+    floats: &[],
+};
+
+static BINARY_FUSE_4: Layout = Layout {
+    kind: FilterKind::BinaryFuse,
+    kind_name: "binary_fuse",
+    name: "BFFilter",
+    fields: &fields::<14>(&binary_fuse_filter::PARAM_NAMES),
+    floats: &[],
+};
+
+fn binary_fuse_layout(arity: usize) -> &'static Layout {
+    if arity == 3 { &BINARY_FUSE_3 } else { &BINARY_FUSE_4 }
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+// Accepts the payload however the format hands it over: borrowed, owned, or as a sequence of u8
+// for formats such as JSON that have no byte type.
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+struct ByteBufVisitor;
+
+impl<'de> Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a byte string")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(bytes.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(bytes))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ByteBuf, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        Ok(ByteBuf(bytes))
+    }
+}
+
+// Splits bytes written by to_bytes back into named fields. The bytes were just written, so the
+// checksum is not computed again; building them still copies the table once (in little-endian
+// order, as the payload needs anyway) and computes the checksum that serializing then drops.
+fn serialize_filter<Z: Serializer>(serializer: Z, layout: &'static Layout, hasher_id: u32, bytes: &[u8]) -> Result<Z::Ok, Z::Error> {
+    let encoded = serialization::decode_unchecked(bytes, layout.kind, hasher_id).map_err(ser::Error::custom)?;
+    if encoded.params.len() != layout.num_params() {
+        return Err(ser::Error::custom("parameter count differs from the filter type"));
+    }
+    let mut state = serializer.serialize_struct(layout.name, layout.fields.len())?;
+    state.serialize_field("kind", layout.kind_name)?;
+    state.serialize_field("hasher_id", &hasher_id)?;
+    state.serialize_field("hasher_state", &encoded.hasher_state)?;
+    state.serialize_field("seed", &encoded.seed)?;
+    for (i, &param) in encoded.params.iter().enumerate() {
+        let field = layout.fields[NUM_HEADER_FIELDS + i];
+        if layout.floats.contains(&i) {
+            state.serialize_field(field, &f64::from_bits(param))?;
+        } else {
+            state.serialize_field(field, &param)?;
+        }
+    }
+    state.serialize_field("payload", &Bytes(encoded.payload))?;
+    state.end()
+}
+
+fn deserialize_filter<'de, D: Deserializer<'de>, T>(deserializer: D, layout: &'static Layout, from_bytes: fn(&[u8]) -> Result<T, SerializationError>) -> Result<T, D::Error> {
+    deserializer.deserialize_struct(layout.name, layout.fields, FilterVisitor { layout, from_bytes })
+}
+
+// Where the next field value comes from: a map after its key, or a sequence in field order.
+trait Source<'de> {
+    type Error: de::Error;
+
+    fn value<V: Deserialize<'de>>(&mut self) -> Result<V, Self::Error>;
+}
+
+struct FromMap<'a, A>(&'a mut A);
+
+impl<'de, A: MapAccess<'de>> Source<'de> for FromMap<'_, A> {
+    type Error = A::Error;
+
+    fn value<V: Deserialize<'de>>(&mut self) -> Result<V, A::Error> {
+        self.0.next_value()
+    }
+}
+
+struct FromSeq<'a, A> {
+    seq: &'a mut A,
+    field: &'static str,
+}
+
+impl<'de, A: SeqAccess<'de>> Source<'de> for FromSeq<'_, A> {
+    type Error = A::Error;
+
+    fn value<V: Deserialize<'de>>(&mut self) -> Result<V, A::Error> {
+        self.seq.next_element()?.ok_or_else(|| de::Error::missing_field(self.field))
+    }
+}
+
+// Maps a field name, or its index for formats that store those, to its position in the layout.
+// Unknown names give None and their values are skipped.
+struct FieldSeed(&'static Layout);
+
+impl<'de> DeserializeSeed<'de> for FieldSeed {
+    type Value = Option<usize>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<usize>, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FieldSeed {
+    type Value = Option<usize>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a field of {}", self.0.name)
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Option<usize>, E> {
+        Ok(self.0.fields.iter().position(|&field| field == name))
+    }
+
+    fn visit_bytes<E: de::Error>(self, name: &[u8]) -> Result<Option<usize>, E> {
+        Ok(self.0.fields.iter().position(|&field| field.as_bytes() == name))
+    }
+
+    fn visit_u64<E: de::Error>(self, index: u64) -> Result<Option<usize>, E> {
+        Ok(usize::try_from(index).ok().filter(|&index| index < self.0.fields.len()))
+    }
+}
+
+#[derive(Default)]
+struct Fields {
+    kind: Option<String>,
+    hasher_id: Option<u32>,
+    hasher_state: Option<u64>,
+    seed: Option<u64>,
+    params: Vec<Option<u64>>,
+    payload: Option<Vec<u8>>,
+}
+
+fn set<T, E: de::Error>(slot: &mut Option<T>, value: T, field: &'static str) -> Result<(), E> {
+    if slot.is_some() {
+        return Err(E::duplicate_field(field));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+impl Fields {
+    fn new(layout: &Layout) -> Self {
+        Fields { params: vec![None; layout.num_params()], ..Fields::default() }
+    }
+
+    fn read<'de, R: Source<'de>>(&mut self, layout: &Layout, index: usize, source: &mut R) -> Result<(), R::Error> {
+        let field = layout.fields[index];
+        match index {
+            0 => set(&mut self.kind, source.value()?, field),
+            1 => set(&mut self.hasher_id, source.value()?, field),
+            2 => set(&mut self.hasher_state, source.value()?, field),
+            3 => set(&mut self.seed, source.value()?, field),
+            _ if index == layout.fields.len() - 1 => set(&mut self.payload, source.value::<ByteBuf>()?.0, field),
+            _ => {
+                let i = index - NUM_HEADER_FIELDS;
+                let param = if layout.floats.contains(&i) { source.value::<f64>()?.to_bits() } else { source.value()? };
+                set(&mut self.params[i], param, field)
+            }
+        }
+    }
+
+    fn finish<T, E: de::Error>(self, layout: &Layout, from_bytes: fn(&[u8]) -> Result<T, SerializationError>) -> Result<T, E> {
+        let missing = |index: usize| E::missing_field(layout.fields[index]);
+        let kind = self.kind.ok_or_else(|| missing(0))?;
+        if kind != layout.kind_name {
+            return Err(E::invalid_value(Unexpected::Str(&kind), &layout.kind_name));
+        }
+        let hasher_id = self.hasher_id.ok_or_else(|| missing(1))?;
+        let hasher_state = self.hasher_state.ok_or_else(|| missing(2))?;
+        let seed = self.seed.ok_or_else(|| missing(3))?;
+        let params = self.params.iter().enumerate().map(|(i, param)| param.ok_or_else(|| missing(NUM_HEADER_FIELDS + i))).collect::<Result<Vec<u64>, E>>()?;
+        let payload = self.payload.ok_or_else(|| missing(layout.fields.len() - 1))?;
+        let bytes = serialization::encode(layout.kind, hasher_id, hasher_state, seed, &params, &payload);
+        from_bytes(&bytes).map_err(E::custom)
+    }
+}
+
+struct FilterVisitor<T> {
+    layout: &'static Layout,
+    from_bytes: fn(&[u8]) -> Result<T, SerializationError>,
+}
+
+impl<'de, T> Visitor<'de> for FilterVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "struct {}", self.layout.name)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let mut fields = Fields::new(self.layout);
+        for (index, &field) in self.layout.fields.iter().enumerate() {
+            fields.read(self.layout, index, &mut FromSeq { seq: &mut seq, field })?;
+        }
+        fields.finish(self.layout, self.from_bytes)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut fields = Fields::new(self.layout);
+        while let Some(key) = map.next_key_seed(FieldSeed(self.layout))? {
+            match key {
+                Some(index) => fields.read(self.layout, index, &mut FromMap(&mut map))?,
+                None => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        fields.finish(self.layout, self.from_bytes)
+    }
+}
+
+impl<S: SerializableHasher> Serialize for BloomFilter<S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serialize_filter(serializer, &BLOOM, S::ID, &self.to_bytes())
+    }
+}
+
+impl<'de, S: SerializableHasher> Deserialize<'de> for BloomFilter<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_filter(deserializer, &BLOOM, Self::from_bytes_with_hasher)
+    }
+}
+
+impl<S: SerializableHasher> Serialize for CountingBloomFilter<S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serialize_filter(serializer, &COUNTING_BLOOM, S::ID, &self.to_bytes())
+    }
+}
+
+impl<'de, S: SerializableHasher> Deserialize<'de> for CountingBloomFilter<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_filter(deserializer, &COUNTING_BLOOM, Self::from_bytes_with_hasher)
+    }
+}
+
+impl<S: SerializableHasher + Clone> Serialize for ScalableBloomFilter<S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serialize_filter(serializer, &SCALABLE_BLOOM, S::ID, &self.to_bytes())
+    }
+}
+
+impl<'de, S: SerializableHasher + Clone> Deserialize<'de> for ScalableBloomFilter<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_filter(deserializer, &SCALABLE_BLOOM, Self::from_bytes_with_hasher)
+    }
+}
+
+impl<S: SerializableHasher> Serialize for BlockedBloomFilter<S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serialize_filter(serializer, &BLOCKED_BLOOM, S::ID, &self.to_bytes())
+    }
+}
+
+impl<'de, S: SerializableHasher> Deserialize<'de> for BlockedBloomFilter<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_filter(deserializer, &BLOCKED_BLOOM, Self::from_bytes_with_hasher)
+    }
+}
+
+impl<S: SerializableHasher> Serialize for CuckooFilter<S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serialize_filter(serializer, &CUCKOO, S::ID, &self.to_bytes())
+    }
+}
+
+impl<'de, S: SerializableHasher> Deserialize<'de> for CuckooFilter<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_filter(deserializer, &CUCKOO, Self::from_bytes_with_hasher)
+    }
+}
+
+impl<F: Fingerprint, S: SerializableHasher> Serialize for XorFilter<F, S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serialize_filter(serializer, &XOR, S::ID, &self.to_bytes())
+    }
+}
+
+impl<'de, F: Fingerprint, S: SerializableHasher> Deserialize<'de> for XorFilter<F, S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_filter(deserializer, &XOR, Self::from_bytes_with_hasher)
+    }
+}
+
+impl<F: Fingerprint, const ARITY: usize, S: SerializableHasher> Serialize for BFFilter<F, ARITY, S> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serialize_filter(serializer, binary_fuse_layout(ARITY), S::ID, &self.to_bytes())
+    }
+}
+
+impl<'de, F: Fingerprint, const ARITY: usize, S: SerializableHasher> Deserialize<'de> for BFFilter<F, ARITY, S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_filter(deserializer, binary_fuse_layout(ARITY), Self::from_bytes_with_hasher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use super::*;
+    use crate::binary_fuse_filter::{BinaryFuse4Wise16, BinaryFuse8};
+    use crate::xor_filter::{Xor16, Xor8};
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(filter: &T, to_bytes: fn(&T) -> Vec<u8>) -> Value {
+        let json = serde_json::to_value(filter).unwrap();
+        let loaded: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(to_bytes(&loaded), to_bytes(filter));
+        json
+    }
+
+    fn keys() -> Vec<u32> {
+        (0..1000).collect()
+    }
+
+    #[test]
+    fn bloom_filter_round_trips_through_json() {
+        let mut filter = BloomFilter::with_seed(1000, 0.01, 7);
+        for i in 0..1000u32 {
+            filter.add(&i);
+        }
+        let json = round_trip(&filter, BloomFilter::to_bytes);
+        assert_eq!(json["kind"], "bloom");
+        assert_eq!(json["seed"], 7);
+        assert_eq!(json["num_items"], 1000);
+        assert_eq!(json["num_hashes"], filter.num_hashes());
+    }
+
+    #[test]
+    fn counting_bloom_filter_round_trips_through_json() {
+        let mut filter = CountingBloomFilter::with_seed(1000, 0.01, 7);
+        for i in 0..1000u32 {
+            filter.add(&i);
+        }
+        let json = round_trip(&filter, CountingBloomFilter::to_bytes);
+        assert_eq!(json["kind"], "counting_bloom");
+        assert_eq!(json["counter_bits"], 4);
+    }
+
+    #[test]
+    fn scalable_bloom_filter_round_trips_through_json() {
+        let mut filter = ScalableBloomFilter::with_params_and_seed(100, 0.01, 2, 0.5, 7);
+        for i in 0..1000u32 {
+            filter.add(&i);
+        }
+        let json = round_trip(&filter, ScalableBloomFilter::to_bytes);
+        assert_eq!(json["false_positive_rate"], 0.01);
+        assert_eq!(json["tightening_ratio"], 0.5);
+        assert_eq!(json["num_stages"], filter.num_stages());
+    }
+
+    #[test]
+    fn blocked_bloom_filter_round_trips_through_json() {
+        let mut filter = BlockedBloomFilter::with_seed(1000, 0.01, 7);
+        for i in 0..1000u32 {
+            filter.add(&i);
+        }
+        let json = round_trip(&filter, BlockedBloomFilter::to_bytes);
+        assert_eq!(json["block_size_bytes"], crate::blocked_bloom_filter::DEFAULT_BLOCK_SIZE_BYTES);
+    }
+
+    #[test]
+    fn cuckoo_filter_round_trips_through_json() {
+        let mut filter = CuckooFilter::with_seed(1000, 0.01, 7);
+        for i in 0..1000u32 {
+            filter.insert(&i).unwrap();
+        }
+        let json = round_trip(&filter, CuckooFilter::to_bytes);
+        assert_eq!(json["num_items"], 1000);
+    }
+
+    #[test]
+    fn xor_filter_round_trips_through_json() {
+        let json = round_trip(&Xor8::with_seed(&keys(), 7).unwrap(), Xor8::to_bytes);
+        assert_eq!(json["fingerprint_bits"], 8);
+        let json = round_trip(&Xor16::with_seed(&keys(), 7).unwrap(), Xor16::to_bytes);
+        assert_eq!(json["fingerprint_bits"], 16);
+    }
+
+    #[test]
+    fn binary_fuse_filter_round_trips_through_json() {
+        let json = round_trip(&BinaryFuse8::with_seed(&keys(), 7).unwrap(), BinaryFuse8::to_bytes);
+        assert_eq!(json["arity"], 3);
+        assert!(json.get("seed3").is_none());
+        let json = round_trip(&BinaryFuse4Wise16::with_seed(&keys(), 7).unwrap(), BinaryFuse4Wise16::to_bytes);
+        assert_eq!(json["arity"], 4);
+        assert!(json.get("seed3").is_some());
+    }
+
+    #[test]
+    fn fields_can_come_in_any_order_or_as_a_sequence() {
+        let filter = Xor8::with_seed(&keys(), 7).unwrap();
+        let json = serde_json::to_value(&filter).unwrap();
+        let Value::Object(map) = &json else { panic!("a filter is written as a struct") };
+        let mut reversed: Vec<(String, Value)> = map.clone().into_iter().collect();
+        reversed.reverse();
+        reversed.push(("comment".to_string(), json!("unknown fields are skipped")));
+        let reversed = Value::Object(reversed.into_iter().collect());
+        assert_eq!(serde_json::from_value::<Xor8>(reversed).unwrap().to_bytes(), filter.to_bytes());
+        let seq = Value::Array(XOR.fields.iter().map(|&field| json[field].clone()).collect());
+        assert_eq!(serde_json::from_value::<Xor8>(seq).unwrap().to_bytes(), filter.to_bytes());
+    }
+
+    #[test]
+    fn rejects_missing_fields_and_another_kind() {
+        let filter = Xor8::with_seed(&keys(), 7).unwrap();
+        let json = serde_json::to_value(&filter).unwrap();
+        let mut missing = json.clone();
+        missing.as_object_mut().unwrap().remove("h1_seed");
+        let err = serde_json::from_value::<Xor8>(missing).err().unwrap();
+        assert!(err.to_string().contains("missing field `h1_seed`"));
+        let mut other_kind = json.clone();
+        other_kind["kind"] = json!("bloom");
+        assert!(serde_json::from_value::<Xor8>(other_kind).is_err());
+        // parameters go through the same checks as from_bytes
+        assert!(serde_json::from_value::<Xor16>(json.clone()).is_err());
+        let mut other_hasher = json;
+        other_hasher["hasher_id"] = json!(2);
+        assert!(serde_json::from_value::<Xor8>(other_hasher).is_err());
+    }
+}
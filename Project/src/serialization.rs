@@ -0,0 +1,489 @@
+//! On-disk format shared by every filter. All integers are little-endian.
+//!
+//! ```text
+//!   offset      size  field
+//!   0           4     magic "RFLT"
+//!   4           2     format version, currently 1
+//!   6           1     filter kind, see FilterKind
+//!   7           1     reserved, 0
+//!   8           4     hasher id, see SerializableHasher
+//!   12          4     number p of parameter words
+//!   16          8     hasher state (its seed)
+//!   24          8     filter seed
+//!   32          8p    parameters, one u64 each, specific to the filter kind
+//!   32+8p       8     payload length L in bytes
+//!   40+8p       L     payload: the filter's table
+//!   40+8p+L     4     CRC-32 (IEEE) of every byte before it
+//! ```
+//!
+//! The payload starts at a multiple of 8 bytes, so a buffer that is itself 8-byte aligned (e.g. a
+//! memory-mapped file) can be read in place.
+//!
+//! Parameter words and payload of each kind:
+//!
+//! ```text
+//!   kind               parameters                                      payload
+//!   1 Bloom            expected_items, num_items, num_bits,            bit vector, u64 words
+//!                      num_hashes, mode (0 seeded, 1 double hashing)
+//!   2 BlockedBloom     expected_items, num_items, num_bits,            cache lines back to back,
+//!                      bits_per_block, block_size_bytes                u64 words
+//!   3 Cuckoo           expected_items, num_buckets, fingerprint_size,  bit-packed table, u64 words
+//!                      bucket_size, num_items, kick_state
+//!   4 Xor              fingerprint_bits, num_keys, num_slots,          fingerprints
+//!                      h0_seed, h1_seed, h2_seed
+//!   5 BinaryFuse       fingerprint_bits, arity, num_keys, num_slots,   fingerprints
+//!                      block_size, one seed per hash (seed0..)
+//!   6 CountingBloom    expected_items, num_items, num_counters,        packed counters, u64 words
+//!                      num_hashes, mode, counter_bits, num_saturated
+//!   7 ScalableBloom    initial_capacity, false_positive_rate,          each stage as a Bloom filter,
+//!                      growth_factor, tightening_ratio (rates as       preceded by its length as
+//!                      f64 bits), num_stages                           a u64
+//! ```
+//!
+//! The serde feature writes each word as a field of the same name.
+
+use std::hash::BuildHasher;
+use std::io::{self, Read, Write};
+use crate::error::SerializationError;
+use crate::fingerprint::Fingerprint;
+use crate::hasher::BuildFastHasher;
+
+/// First four bytes of every serialized filter.
+pub const MAGIC: [u8; 4] = *b"RFLT";
+/// Version of the format written by this crate.
+pub const FORMAT_VERSION: u16 = 1;
+const HEADER_SIZE: usize = 32;
+
+/// Which filter a serialized buffer holds, stored in its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FilterKind {
+    /// BloomFilter.
+    Bloom = 1,
+    /// BlockedBloomFilter.
+    BlockedBloom = 2,
+    /// CuckooFilter.
+    Cuckoo = 3,
+    /// XorFilter.
+    Xor = 4,
+    /// BFFilter.
+    BinaryFuse = 5,
+    /// CountingBloomFilter.
+    CountingBloom = 6,
+    /// ScalableBloomFilter.
+    ScalableBloom = 7,
+}
+
+/// A hasher that can be saved with a filter and rebuilt when loading it. The id tells hashers apart
+/// so that a filter is never queried with a hasher other than the one it was built with.
+pub trait SerializableHasher: BuildHasher + Sized {
+    /// Identifies the hasher in the header. Every implementation needs its own id.
+    const ID: u32;
+
+    /// The hasher's state, e.g. its seed, stored in the header.
+    fn state(&self) -> u64;
+
+    /// Rebuilds the hasher from a state returned by state().
+    fn from_state(state: u64) -> Self;
+}
+
+impl SerializableHasher for BuildFastHasher {
+    const ID: u32 = 1;
+
+    fn state(&self) -> u64 {
+        self.seed()
+    }
+
+    fn from_state(state: u64) -> Self {
+        BuildFastHasher::with_seed(state)
+    }
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// CRC-32 as used by zlib and PNG.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Everything in a serialized filter apart from the framing.
+pub struct Encoded<'a> {
+    /// State to rebuild the hasher from, see SerializableHasher::from_state.
+    pub hasher_state: u64,
+    /// Seed the filter was built with.
+    pub seed: u64,
+    /// Parameter words, specific to the filter kind.
+    pub params: Vec<u64>,
+    /// The filter's table.
+    pub payload: &'a [u8],
+}
+
+/// Frames params and payload with the header and checksum described above.
+pub fn encode(kind: FilterKind, hasher_id: u32, hasher_state: u64, seed: u64, params: &[u64], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE + 8 * params.len() + 8 + payload.len() + 4);
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.push(kind as u8);
+    out.push(0);
+    out.extend_from_slice(&hasher_id.to_le_bytes());
+    out.extend_from_slice(&(params.len() as u32).to_le_bytes());
+    out.extend_from_slice(&hasher_state.to_le_bytes());
+    out.extend_from_slice(&seed.to_le_bytes());
+    for param in params {
+        out.extend_from_slice(&param.to_le_bytes());
+    }
+    out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    out.extend_from_slice(payload);
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut word = [0u8; 8];
+    word.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(word)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0u8; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
+
+/// Checks the framing of bytes against the expected kind and hasher and splits it into its parts.
+/// Bytes after the checksum are ignored.
+pub fn decode(bytes: &[u8], kind: FilterKind, hasher_id: u32) -> Result<Encoded<'_>, SerializationError> {
+    decode_frame(bytes, kind, hasher_id, true)
+}
+
+/// Same as decode without computing the checksum, so only the header and the lengths are read.
+/// The parts are still in bounds, but corrupted parameters or payload go unnoticed.
+pub fn decode_unchecked(bytes: &[u8], kind: FilterKind, hasher_id: u32) -> Result<Encoded<'_>, SerializationError> {
+    decode_frame(bytes, kind, hasher_id, false)
+}
+
+fn decode_frame(bytes: &[u8], kind: FilterKind, hasher_id: u32, verify_checksum: bool) -> Result<Encoded<'_>, SerializationError> {
+    if bytes.len() < 4 {
+        return Err(SerializationError::Truncated);
+    }
+    if bytes[..4] != MAGIC {
+        return Err(SerializationError::BadMagic);
+    }
+    if bytes.len() < HEADER_SIZE {
+        return Err(SerializationError::Truncated);
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != FORMAT_VERSION {
+        return Err(SerializationError::UnsupportedVersion(version));
+    }
+    if bytes[6] != kind as u8 {
+        return Err(SerializationError::KindMismatch { expected: kind as u8, found: bytes[6] });
+    }
+    let found_hasher = read_u32(bytes, 8);
+    if found_hasher != hasher_id {
+        return Err(SerializationError::HasherMismatch { expected: hasher_id, found: found_hasher });
+    }
+    let num_params = read_u32(bytes, 12) as usize;
+    let payload_start = HEADER_SIZE + 8 * num_params + 8;
+    if bytes.len() < payload_start {
+        return Err(SerializationError::Truncated);
+    }
+    let payload_len = to_usize(read_u64(bytes, payload_start - 8))?;
+    let end = payload_start.checked_add(payload_len).ok_or(SerializationError::Truncated)?;
+    if bytes.len() < end.saturating_add(4) {
+        return Err(SerializationError::Truncated);
+    }
+    if verify_checksum {
+        let stored = read_u32(bytes, end);
+        let computed = crc32(&bytes[..end]);
+        if stored != computed {
+            return Err(SerializationError::ChecksumMismatch { expected: stored, found: computed });
+        }
+    }
+    Ok(Encoded {
+        hasher_state: read_u64(bytes, 16),
+        seed: read_u64(bytes, 24),
+        params: (0..num_params).map(|i| read_u64(bytes, HEADER_SIZE + 8 * i)).collect(),
+        payload: &bytes[payload_start..end],
+    })
+}
+
+/// Writes bytes to writer and flushes it.
+pub fn write_all<W: Write>(mut writer: W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(bytes)?;
+    writer.flush()
+}
+
+/// Reads reader until its end.
+pub fn read_all<R: Read>(mut reader: R) -> Result<Vec<u8>, SerializationError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Converts a stored u64 to usize, failing with InvalidParameters if it does not fit.
+pub fn to_usize(value: u64) -> Result<usize, SerializationError> {
+    usize::try_from(value).map_err(|_| SerializationError::InvalidParameters("value does not fit in usize"))
+}
+
+// Views bytes in place as a slice of T, None unless bytes is aligned for T, a whole number of T
+// and the target is little-endian like the format.
+//
+// Safety: every bit pattern must be a valid T, which holds for the primitive integers.
+pub(crate) unsafe fn cast_slice<T>(bytes: &[u8]) -> Option<&[T]> {
+    let size = std::mem::size_of::<T>();
+    if cfg!(target_endian = "big") || !(bytes.as_ptr() as usize).is_multiple_of(std::mem::align_of::<T>()) || !bytes.len().is_multiple_of(size) {
+        return None;
+    }
+    Some(std::slice::from_raw_parts(bytes.as_ptr() as *const T, bytes.len() / size))
+}
+
+/// Views a payload of u64 words in place.
+pub fn cast_words(payload: &[u8]) -> Result<&[u64], SerializationError> {
+    if !payload.len().is_multiple_of(8) {
+        return Err(SerializationError::InvalidParameters("payload is not a whole number of words"));
+    }
+    // every bit pattern is a valid u64
+    unsafe { cast_slice::<u64>(payload) }.ok_or(SerializationError::Misaligned)
+}
+
+/// Little-endian bytes of words.
+pub fn words_to_bytes(words: &[u64]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// Fills words from a payload of exactly words.len() * 8 bytes.
+pub fn bytes_to_words(payload: &[u8], words: &mut [u64]) -> Result<(), SerializationError> {
+    if payload.len() != words.len() * 8 {
+        return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
+    }
+    for (word, chunk) in words.iter_mut().zip(payload.chunks_exact(8)) {
+        *word = read_u64(chunk, 0);
+    }
+    Ok(())
+}
+
+/// Little-endian bytes of fingerprints.
+pub fn fingerprints_to_bytes<F: Fingerprint>(fingerprints: &[F]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(fingerprints.len() * F::BITS as usize / 8);
+    for &fp in fingerprints {
+        fp.write_le(&mut out);
+    }
+    out
+}
+
+/// Reads exactly len fingerprints.
+pub fn bytes_to_fingerprints<F: Fingerprint>(payload: &[u8], len: usize) -> Result<Vec<F>, SerializationError> {
+    let width = F::BITS as usize / 8;
+    if Some(payload.len()) != len.checked_mul(width) {
+        return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
+    }
+    Ok(payload.chunks_exact(width).map(F::read_le).collect())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const HASHER: u32 = BuildFastHasher::ID;
+
+    /// Checks that load accepts bytes but rejects them cut short, with a flipped payload byte, or
+    /// relabelled as another kind of filter or another hasher.
+    pub(crate) fn assert_rejects_corruption<T>(bytes: &[u8], load: impl Fn(&[u8]) -> Result<T, SerializationError>) {
+        assert!(load(bytes).is_ok());
+        assert!(matches!(load(&bytes[..bytes.len() - 1]), Err(SerializationError::Truncated)));
+        let mut flipped = bytes.to_vec();
+        let last_payload_byte = flipped.len() - 5;
+        flipped[last_payload_byte] ^= 1;
+        assert!(matches!(load(&flipped), Err(SerializationError::ChecksumMismatch { .. })));
+        let mut other_kind = bytes.to_vec();
+        other_kind[6] = if other_kind[6] == FilterKind::Bloom as u8 { FilterKind::Cuckoo as u8 } else { FilterKind::Bloom as u8 };
+        reseal(&mut other_kind);
+        assert!(matches!(load(&other_kind), Err(SerializationError::KindMismatch { .. })));
+        let mut other_hasher = bytes.to_vec();
+        other_hasher[8..12].copy_from_slice(&(HASHER + 1).to_le_bytes());
+        reseal(&mut other_hasher);
+        assert!(matches!(load(&other_hasher), Err(SerializationError::HasherMismatch { .. })));
+    }
+
+    /// Loads bytes with from_bytes and checks that the filter writes them back unchanged through
+    /// write_to, that read_from loads what write_to wrote, and that corrupted copies are rejected.
+    /// Returns the loaded filter for checks specific to its type.
+    pub(crate) fn assert_round_trips<T>(
+        bytes: &[u8],
+        from_bytes: impl Fn(&[u8]) -> Result<T, SerializationError>,
+        read_from: impl Fn(&[u8]) -> Result<T, SerializationError>,
+        write_to: impl Fn(&T, &mut Vec<u8>) -> io::Result<()>,
+    ) -> T {
+        let loaded = from_bytes(bytes).unwrap();
+        let mut written = Vec::new();
+        write_to(&loaded, &mut written).unwrap();
+        assert_eq!(written, bytes);
+        let read = read_from(&written).unwrap();
+        let mut rewritten = Vec::new();
+        write_to(&read, &mut rewritten).unwrap();
+        assert_eq!(rewritten, bytes);
+        assert_rejects_corruption(bytes, &from_bytes);
+        loaded
+    }
+
+    fn sample() -> Vec<u8> {
+        encode(FilterKind::Bloom, HASHER, 11, 22, &[1, 2, 3], &[0xab; 16])
+    }
+
+    // Rewrites the checksum so that only the check under test can fail.
+    fn reseal(bytes: &mut [u8]) {
+        let end = bytes.len() - 4;
+        let checksum = crc32(&bytes[..end]);
+        bytes[end..].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    /// Calls check with a copy of bytes starting offset bytes past an 8-byte boundary.
+    pub(crate) fn at_offset<R>(bytes: &[u8], offset: usize, check: impl FnOnce(&[u8]) -> R) -> R {
+        let mut words = vec![0u64; (offset + bytes.len()).div_ceil(8)];
+        let storage = unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, words.len() * 8) };
+        storage[offset..offset + bytes.len()].copy_from_slice(bytes);
+        check(&storage[offset..offset + bytes.len()])
+    }
+
+    #[test]
+    fn crc32_matches_the_reference_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn decode_returns_what_encode_was_given() {
+        let bytes = sample();
+        assert_eq!(bytes.len(), HEADER_SIZE + 3 * 8 + 8 + 16 + 4);
+        // the payload starts on a word boundary
+        assert_eq!((bytes.len() - 4 - 16) % 8, 0);
+        let encoded = decode(&bytes, FilterKind::Bloom, HASHER).unwrap();
+        assert_eq!(encoded.hasher_state, 11);
+        assert_eq!(encoded.seed, 22);
+        assert_eq!(encoded.params, [1, 2, 3]);
+        assert_eq!(encoded.payload, &[0xab; 16]);
+    }
+
+    #[test]
+    fn trailing_bytes_are_ignored() {
+        let mut bytes = sample();
+        bytes.extend_from_slice(b"trailer");
+        assert!(decode(&bytes, FilterKind::Bloom, HASHER).is_ok());
+    }
+
+    #[test]
+    fn every_truncation_is_rejected() {
+        let bytes = sample();
+        for len in 0..bytes.len() {
+            assert!(matches!(decode(&bytes[..len], FilterKind::Bloom, HASHER), Err(SerializationError::Truncated)), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn a_huge_payload_length_is_truncated_not_a_panic() {
+        let mut bytes = sample();
+        let offset = HEADER_SIZE + 3 * 8;
+        bytes[offset..offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(decode(&bytes, FilterKind::Bloom, HASHER), Err(SerializationError::Truncated)));
+    }
+
+    #[test]
+    fn wrong_magic_is_rejected() {
+        let mut bytes = sample();
+        bytes[0] = b'X';
+        assert!(matches!(decode(&bytes, FilterKind::Bloom, HASHER), Err(SerializationError::BadMagic)));
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut bytes = sample();
+        bytes[4..6].copy_from_slice(&2u16.to_le_bytes());
+        reseal(&mut bytes);
+        assert!(matches!(decode(&bytes, FilterKind::Bloom, HASHER), Err(SerializationError::UnsupportedVersion(2))));
+    }
+
+    #[test]
+    fn wrong_kind_is_rejected() {
+        let bytes = sample();
+        let err = decode(&bytes, FilterKind::Cuckoo, HASHER).err().unwrap();
+        assert!(matches!(err, SerializationError::KindMismatch { expected: 3, found: 1 }));
+    }
+
+    #[test]
+    fn wrong_hasher_is_rejected() {
+        let bytes = sample();
+        let err = decode(&bytes, FilterKind::Bloom, HASHER + 1).err().unwrap();
+        assert!(matches!(err, SerializationError::HasherMismatch { expected, found } if expected == HASHER + 1 && found == HASHER));
+    }
+
+    #[test]
+    fn any_flipped_bit_fails_the_checksum() {
+        let bytes = sample();
+        // past the fields checked before the checksum: hasher state, seed, parameters and payload
+        for i in 16..bytes.len() - 4 {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= 0x10;
+            let result = decode(&corrupted, FilterKind::Bloom, HASHER);
+            if i < HEADER_SIZE + 3 * 8 {
+                assert!(matches!(result, Err(SerializationError::ChecksumMismatch { .. })), "byte {}", i);
+            } else {
+                // a corrupted payload length may also run past the end
+                assert!(matches!(result, Err(SerializationError::ChecksumMismatch { .. }) | Err(SerializationError::Truncated)), "byte {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_unchecked_skips_only_the_checksum() {
+        let mut bytes = sample();
+        let last_payload_byte = bytes.len() - 5;
+        bytes[last_payload_byte] ^= 1;
+        assert!(matches!(decode(&bytes, FilterKind::Bloom, HASHER), Err(SerializationError::ChecksumMismatch { .. })));
+        assert_eq!(decode_unchecked(&bytes, FilterKind::Bloom, HASHER).unwrap().payload[15], 0xaa);
+        assert!(matches!(decode_unchecked(&bytes[..bytes.len() - 1], FilterKind::Bloom, HASHER), Err(SerializationError::Truncated)));
+        assert!(matches!(decode_unchecked(&bytes, FilterKind::Cuckoo, HASHER), Err(SerializationError::KindMismatch { .. })));
+        assert!(matches!(decode_unchecked(&bytes, FilterKind::Bloom, HASHER + 1), Err(SerializationError::HasherMismatch { .. })));
+    }
+
+    #[test]
+    fn cast_words_needs_an_aligned_whole_number_of_words() {
+        let words = [1u64, 2, 3];
+        let bytes = unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, 24) };
+        assert_eq!(cast_words(bytes).unwrap(), &words);
+        assert!(matches!(cast_words(&bytes[1..17]), Err(SerializationError::Misaligned)));
+        assert!(matches!(cast_words(&bytes[..12]), Err(SerializationError::InvalidParameters(_))));
+    }
+
+    #[test]
+    fn words_and_fingerprints_round_trip() {
+        let words = [0u64, 1, u64::MAX, 0x0123_4567_89ab_cdef];
+        let mut back = [0u64; 4];
+        bytes_to_words(&words_to_bytes(&words), &mut back).unwrap();
+        assert_eq!(back, words);
+        let fingerprints = [0u16, 1, 0xbeef, u16::MAX];
+        assert_eq!(bytes_to_fingerprints::<u16>(&fingerprints_to_bytes(&fingerprints), 4).unwrap(), fingerprints);
+        assert!(bytes_to_fingerprints::<u16>(&fingerprints_to_bytes(&fingerprints), 3).is_err());
+    }
+}
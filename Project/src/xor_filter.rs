@@ -1,97 +1,136 @@
-use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
-use rand::Rng;
-use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
 use std::time::{Duration, Instant};
+use crate::error::{ConstructionError, SerializationError};
+use crate::filter::Filter;
+use crate::fingerprint::Fingerprint;
+use crate::hasher::DefaultBuildHasher;
+use crate::reduce::fastrange;
+use crate::seed::{random_seed, SplitMix64};
+use crate::serialization::{self, bytes_to_fingerprints, fingerprints_to_bytes, to_usize, Encoded, FilterKind, SerializableHasher};
 
-struct XorFilter {
-    b: Vec<u8>,
+const MAX_ATTEMPTS: usize = 100;// seeds tried before construction gives up
+
+/// F is the fingerprint type: u8, u16 or u32, giving a false positive rate of 1/2^8, 1/2^16 or 1/2^32.
+/// S builds the hasher used on keys.
+pub struct XorFilter<F: Fingerprint = u8, S = DefaultBuildHasher> {
+    b: Vec<F>,
     h0_seed: u64,
     h1_seed: u64,
     h2_seed: u64,
     c: usize,
     block_size: usize,
+    num_keys: usize,
+    seed: u64,// every attempt's hash seeds are drawn from it
+    hash_builder: S,
+}
+
+/// Xor filter with 8-bit fingerprints, false positive rate about 0.39%.
+pub type Xor8 = XorFilter<u8>;
+/// Xor filter with 16-bit fingerprints.
+pub type Xor16 = XorFilter<u16>;
+/// Xor filter with 32-bit fingerprints.
+pub type Xor32 = XorFilter<u32>;
+
+// Construction and lookups only ever look at this 64-bit hash of a key.
+fn key_hash<T: Hash + ?Sized, S: BuildHasher>(hash_builder: &S, key: &T) -> u64 {
+    hash_builder.hash_one(key)
 }
 
-impl XorFilter {
-    fn fingerprint<T: Hash+Clone+Eq>(&self, x: &T) -> u8 {
-        let mut s = DefaultHasher::new();
-        x.hash(&mut s);
-        let hash_value = s.finish();
-        let mask = (1 << 7) - 1; // for 7-bit fingerprints aiming for 0.0078 fpr
-        ((hash_value >> (64 - 7)) & mask) as u8
+// Slot of a key in the given block of block_size slots.
+fn slot(seed: u64, hash: u64, block_size: usize, block: usize) -> usize {
+    fastrange(seed.wrapping_mul(hash), block_size) + block * block_size
+}
+
+impl<F: Fingerprint> XorFilter<F> {
+    /// Builds a filter from keys; repeated keys are stored once.
+    pub fn new<T: Hash>(keys: &[T]) -> Result<Self, ConstructionError> {
+        Self::with_hasher(keys, random_seed(), DefaultBuildHasher::default())
     }
 
-    fn h0<T: Hash+Clone+Eq>(&self, item: &T) -> usize {
-        let mut hasher = DefaultHasher::new();
-        item.hash(&mut hasher);
-        let hash = hasher.finish();
-        (((self.h0_seed.wrapping_mul(hash)) >> 32) % self.block_size as u64) as usize
+    /// Same keys and seed always give the same filter.
+    pub fn with_seed<T: Hash>(keys: &[T], seed: u64) -> Result<Self, ConstructionError> {
+        Self::with_hasher(keys, seed, DefaultBuildHasher::default())
     }
 
-    fn h1<T: Hash+Clone+Eq>(&self, item: &T) -> usize {
-        let mut hasher = DefaultHasher::new();
-        item.hash(&mut hasher);
-        let hash = hasher.finish();
-        (((self.h1_seed.wrapping_mul(hash)) >> 32) % self.block_size as u64) as usize + self.block_size
+    /// Builds a filter from key hashes computed with the default hasher, e.g. by
+    /// DefaultBuildHasher::default().hash_one(key).
+    pub fn from_hashes(key_hashes: &[u64]) -> Result<Self, ConstructionError> {
+        Self::from_hashes_and_seed(key_hashes, random_seed())
     }
 
-    fn h2<T: Hash+Clone+Eq>(&self, item: &T) -> usize {
-        let mut hasher = DefaultHasher::new();
-        item.hash(&mut hasher);
-        let hash = hasher.finish();
-        (((self.h2_seed.wrapping_mul(hash)) >> 32) % self.block_size as u64) as usize + 2 * self.block_size
+    /// Same as from_hashes with a fixed seed.
+    pub fn from_hashes_and_seed(key_hashes: &[u64], seed: u64) -> Result<Self, ConstructionError> {
+        Self::from_hashes_with_hasher(key_hashes, seed, DefaultBuildHasher::default())
     }
+}
 
-    fn map<T: Hash+Clone+Eq>(&self, keys: &[T]) -> (bool, Vec<(T, usize)>) {
-        let mut h: Vec<Vec<T>> = vec![Vec::new(); self.c];
-        for x in keys {
-            h[self.h0(x)].push(x.clone());
-            h[self.h1(x)].push(x.clone());
-            h[self.h2(x)].push(x.clone());
-        }
+impl<F: Fingerprint, S: BuildHasher> XorFilter<F, S> {
+    fn fingerprint(&self, hash: u64) -> F {
+        F::from_hash(hash)
+    }
+
+    fn h0(&self, hash: u64) -> usize {
+        slot(self.h0_seed, hash, self.block_size, 0)
+    }
+
+    fn h1(&self, hash: u64) -> usize {
+        slot(self.h1_seed, hash, self.block_size, 1)
+    }
 
-        let mut queue: VecDeque<usize> = VecDeque::new();
+    fn h2(&self, hash: u64) -> usize {
+        slot(self.h2_seed, hash, self.block_size, 2)
+    }
 
-        for i in 0..h.len() {
-            if h[i].len() == 1 {
-                queue.push_back(i);
+    // Peeling as in the xor filter paper: every slot keeps how many keys map to it and the xor of
+    // their hashes, so a slot with count 1 directly yields the hash of its only key.
+    fn map(&self, hashes: &[u64]) -> (bool, Vec<(u64, usize)>) {
+        let mut count: Vec<u32> = vec![0; self.c];
+        let mut xor_hash: Vec<u64> = vec![0; self.c];
+        for &hash in hashes {
+            for idx in [self.h0(hash), self.h1(hash), self.h2(hash)] {
+                count[idx] += 1;
+                xor_hash[idx] ^= hash;
             }
         }
-        let mut stack: Vec<(T, usize)> = Vec::new();
-        while let Some(i) = queue.pop_front() {
-            if h[i].len() == 1 {
-                if let Some(x) = h[i].get(0).cloned(){
-                    stack.push((x.clone(), i));
-                    for &idx in &[self.h0(&x), self.h1(&x), self.h2(&x)] {
-                        if let Some(pos) = h[idx].iter().position(|item| *item == x) {
-                            h[idx].remove(pos);
-                        }
-                        if h[idx].len() == 1 {
-                            queue.push_back(idx);
-                        }
+
+        let mut queue: Vec<usize> = (0..self.c).filter(|&i| count[i] == 1).collect();
+        let mut stack: Vec<(u64, usize)> = Vec::with_capacity(hashes.len());
+        while let Some(i) = queue.pop() {
+            if count[i] == 1 {
+                let hash = xor_hash[i];
+                stack.push((hash, i));
+                for idx in [self.h0(hash), self.h1(hash), self.h2(hash)] {
+                    count[idx] -= 1;
+                    xor_hash[idx] ^= hash;
+                    if count[idx] == 1 {
+                        queue.push(idx);
                     }
                 }
             }
         }
-        let mut new_stack = stack.clone();
-        new_stack.reverse();//make sure the stack behave like FILO.
-        (stack.len() == keys.len(), new_stack)
+        (stack.len() == hashes.len(), stack)
     }
 
-    fn assign<T:Hash+Clone+Eq>(&mut self, stack: Vec<(T, usize)>) {
-        for (x, i) in stack {
-            let fp = self.fingerprint(&x);
-            self.b[i] = 0;
-            self.b[i] = fp ^ self.b[self.h0(&x)] ^ self.b[self.h1(&x)] ^ self.b[self.h2(&x)];
+    fn assign(&mut self, stack: Vec<(u64, usize)>) {
+        for (hash, i) in stack.into_iter().rev() {//the last key peeled is assigned first.
+            let fp = self.fingerprint(hash);
+            self.b[i] = F::default();
+            self.b[i] = fp ^ self.b[self.h0(hash)] ^ self.b[self.h1(hash)] ^ self.b[self.h2(hash)];
         }
     }
 
-    fn contains<T:Hash+Clone+Eq>(&self, key: &T) -> bool {
-        let fp = self.fingerprint(key); // Calculate fingerprint of the key
-        let h0_index = self.h0(key); // Get index from h0
-        let h1_index = self.h1(key); // Get index from h1
-        let h2_index = self.h2(key); // Get index from h2
+    /// Returns true if the key may have been in the construction set, false if it definitely was not.
+    pub fn contains<T: Hash + ?Sized>(&self, key: &T) -> bool {
+        self.contains_hash(key_hash(&self.hash_builder, key))
+    }
+
+    /// Same as contains for a caller that already hashed the key with this filter's hasher.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        let fp = self.fingerprint(hash); // Calculate fingerprint of the key
+        let h0_index = self.h0(hash); // Get index from h0
+        let h1_index = self.h1(hash); // Get index from h1
+        let h2_index = self.h2(hash); // Get index from h2
 
         // XOR the values stored at these indices in array `B`
         let computed_fp = self.b[h0_index] ^ self.b[h1_index] ^ self.b[h2_index];
@@ -100,30 +139,294 @@ impl XorFilter {
         computed_fp == fp
     }
 
-    fn new<T: Hash+Clone+Eq>(keys: &[T]) -> Self {
-        let mut rng = rand::thread_rng();
-        let c = (keys.len() as f64*1.23).floor() as usize + 32;
+    /// Builds a filter from keys hashed with hash_builder; repeated keys are stored once.
+    pub fn with_hasher<T: Hash>(keys: &[T], seed: u64, hash_builder: S) -> Result<Self, ConstructionError> {
+        let hashes: Vec<u64> = keys.iter().map(|key| key_hash(&hash_builder, key)).collect();
+        Self::from_hashes_with_hasher(&hashes, seed, hash_builder)
+    }
+
+    /// Builds a filter from key hashes already computed with hash_builder.
+    pub fn from_hashes_with_hasher(key_hashes: &[u64], seed: u64, hash_builder: S) -> Result<Self, ConstructionError> {
+        let hashes = unique_hashes(key_hashes);
+        let c = (hashes.len() as f64*1.23).floor() as usize + 32;
+        Self::build(&hashes, c, seed, hash_builder)
+    }
+
+    // Retries seeds until the distinct hashes peel in a table of c slots.
+    fn build(hashes: &[u64], c: usize, seed: u64, hash_builder: S) -> Result<Self, ConstructionError> {
+        let mut rng = SplitMix64::new(seed);
         let block_size = c / 3 ;
-        loop {
-            let mut filter = XorFilter {
-                b: vec![0; c],
-                h0_seed:rng.gen::<u64>() | 1,
-                h1_seed:rng.gen::<u64>() | 1,
-                h2_seed:rng.gen::<u64>() | 1,
-                c,
-                block_size,
-            };
-
-            let (success, stack) = filter.map(keys);
+        let mut filter = XorFilter {
+            b: vec![F::default(); c],
+            h0_seed: 0,
+            h1_seed: 0,
+            h2_seed: 0,
+            c,
+            block_size,
+            num_keys: hashes.len(),
+            seed,
+            hash_builder,
+        };
+        for _ in 0..MAX_ATTEMPTS {
+            filter.h0_seed = rng.next_odd();
+            filter.h1_seed = rng.next_odd();
+            filter.h2_seed = rng.next_odd();
+
+            let (success, stack) = filter.map(hashes);
             if success {
                 filter.assign(stack);
-                return filter;
+                return Ok(filter);
             }
             // If not successful, loop will continue and try with new seeds
         }
+        Err(ConstructionError::TooManyRetries { attempts: MAX_ATTEMPTS })
+    }
+
+    /// Number of distinct keys the filter was built from.
+    pub fn len(&self) -> usize {
+        self.num_keys
+    }
+
+    /// True if the filter was built from no keys.
+    pub fn is_empty(&self) -> bool {
+        self.num_keys == 0
+    }
+
+    /// Seed the filter was built with; passing it back to a constructor rebuilds the same filter.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Bytes of the fingerprint table.
+    pub fn size_in_bytes(&self) -> usize {
+        self.b.len() * std::mem::size_of::<F>()
+    }
+
+    /// One in 2^BITS negative keys matches the fingerprint by chance.
+    pub fn expected_fpr(&self) -> f64 {
+        1f64 / 2f64.powi(F::BITS as i32)
+    }
+}
+
+impl<T: Hash + ?Sized, F: Fingerprint, S: BuildHasher> Filter<T> for XorFilter<F, S> {
+    fn contains(&self, item: &T) -> bool {
+        XorFilter::<F, S>::contains(self, item)
+    }
+
+    fn len(&self) -> usize {
+        XorFilter::<F, S>::len(self)
+    }
+
+    // A static filter holds exactly the keys it was built from.
+    fn capacity(&self) -> usize {
+        XorFilter::<F, S>::len(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        XorFilter::<F, S>::size_in_bytes(self)
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        XorFilter::<F, S>::expected_fpr(self)
+    }
+}
+
+struct Params {
+    num_keys: usize,
+    c: usize,
+    seeds: [u64; 3],
+}
+
+impl Params {
+    fn decode<F: Fingerprint>(params: &[u64]) -> Result<Params, SerializationError> {
+        let [bits, num_keys, c, h0_seed, h1_seed, h2_seed] = params[..] else {
+            return Err(SerializationError::InvalidParameters("a xor filter has 6 parameters"));
+        };
+        if bits != F::BITS as u64 {
+            return Err(SerializationError::InvalidParameters("fingerprint width differs from the filter type"));
+        }
+        let c = to_usize(c)?;
+        if c < 3 {
+            return Err(SerializationError::InvalidParameters("xor filter parameters out of range"));
+        }
+        Ok(Params { num_keys: to_usize(num_keys)?, c, seeds: [h0_seed, h1_seed, h2_seed] })
+    }
+}
+
+// Names of the parameter words, in the order to_bytes writes them. serde_support uses them as field names.
+pub(crate) const PARAM_NAMES: [&str; 6] = ["fingerprint_bits", "num_keys", "num_slots", "h0_seed", "h1_seed", "h2_seed"];
+
+// num_slots is c and the payload is the fingerprint array. The hash seeds are stored since the
+// attempt that found them is not.
+impl<F: Fingerprint, S: SerializableHasher> XorFilter<F, S> {
+    /// Serializes the filter in the format described in serialization.rs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let params: [u64; PARAM_NAMES.len()] = [F::BITS as u64, self.num_keys as u64, self.c as u64, self.h0_seed, self.h1_seed, self.h2_seed];
+        serialization::encode(FilterKind::Xor, S::ID, self.hash_builder.state(), self.seed, &params, &fingerprints_to_bytes(&self.b))
+    }
+
+    /// Writes to_bytes() to writer.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        serialization::write_all(writer, &self.to_bytes())
+    }
+
+    /// Loads a filter written by to_bytes, checking its framing, checksum and fingerprint width.
+    pub fn from_bytes_with_hasher(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let encoded = serialization::decode(bytes, FilterKind::Xor, S::ID)?;
+        let params = Params::decode::<F>(&encoded.params)?;
+        Ok(XorFilter {
+            b: bytes_to_fingerprints(encoded.payload, params.c)?,
+            h0_seed: params.seeds[0],
+            h1_seed: params.seeds[1],
+            h2_seed: params.seeds[2],
+            c: params.c,
+            block_size: params.c / 3,
+            num_keys: params.num_keys,
+            seed: encoded.seed,
+            hash_builder: S::from_state(encoded.hasher_state),
+        })
+    }
+
+    /// Reads all of reader and loads it with from_bytes_with_hasher.
+    pub fn read_from_with_hasher<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
     }
 }
 
+impl<F: Fingerprint> XorFilter<F> {
+    /// Loads a filter built with the default hasher, see from_bytes_with_hasher.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(bytes)
+    }
+
+    /// Reads all of reader and loads it with from_bytes.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(&serialization::read_all(reader)?)
+    }
+}
+
+/// Read-only xor filter answering queries straight from the bytes written by XorFilter::to_bytes,
+/// e.g. a memory-mapped file, without copying the fingerprints. The bytes must start at an address
+/// aligned for F. Opening checks the checksum, which reads the whole buffer once;
+/// from_bytes_unchecked skips it.
+pub struct XorFilterRef<'a, F: Fingerprint = u8, S = DefaultBuildHasher> {
+    b: &'a [F],
+    seeds: [u64; 3],
+    block_size: usize,
+    num_keys: usize,
+    seed: u64,
+    hash_builder: S,
+}
+
+impl<'a, F: Fingerprint> XorFilterRef<'a, F> {
+    /// Opens a view over a filter built with the default hasher, see from_bytes_with_hasher.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_with_hasher(bytes)
+    }
+
+    /// Opens a view over a filter built with the default hasher, see from_bytes_unchecked_with_hasher.
+    pub fn from_bytes_unchecked(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::from_bytes_unchecked_with_hasher(bytes)
+    }
+}
+
+impl<'a, F: Fingerprint, S: SerializableHasher> XorFilterRef<'a, F, S> {
+    /// Opens a view over bytes written by XorFilter::to_bytes without copying the fingerprints.
+    pub fn from_bytes_with_hasher(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::open(serialization::decode(bytes, FilterKind::Xor, S::ID)?)
+    }
+
+    /// Same as from_bytes_with_hasher without verifying the checksum, so opening does not read the
+    /// whole buffer. The header, lengths and alignment are still checked and queries stay in bounds,
+    /// but a corrupted fingerprint table gives wrong answers instead of an error: only use it on bytes known
+    /// to be intact, e.g. opened once with from_bytes after they were written.
+    pub fn from_bytes_unchecked_with_hasher(bytes: &'a [u8]) -> Result<Self, SerializationError> {
+        Self::open(serialization::decode_unchecked(bytes, FilterKind::Xor, S::ID)?)
+    }
+
+    fn open(encoded: Encoded<'a>) -> Result<Self, SerializationError> {
+        let params = Params::decode::<F>(&encoded.params)?;
+        if Some(encoded.payload.len()) != params.c.checked_mul(F::BITS as usize / 8) {
+            return Err(SerializationError::InvalidParameters("payload length does not match the parameters"));
+        }
+        Ok(XorFilterRef {
+            b: F::cast_slice(encoded.payload).ok_or(SerializationError::Misaligned)?,
+            seeds: params.seeds,
+            block_size: params.c / 3,
+            num_keys: params.num_keys,
+            seed: encoded.seed,
+            hash_builder: S::from_state(encoded.hasher_state),
+        })
+    }
+
+    /// Same as XorFilter::contains.
+    pub fn contains<T: Hash + ?Sized>(&self, key: &T) -> bool {
+        self.contains_hash(key_hash(&self.hash_builder, key))
+    }
+
+    /// Same as XorFilter::contains_hash.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        let computed_fp = (0..3).fold(F::default(), |acc, i| acc ^ self.b[slot(self.seeds[i], hash, self.block_size, i)]);
+        computed_fp == F::from_hash(hash)
+    }
+
+    /// Number of distinct keys the filter was built from.
+    pub fn len(&self) -> usize {
+        self.num_keys
+    }
+
+    /// True if the filter was built from no keys.
+    pub fn is_empty(&self) -> bool {
+        self.num_keys == 0
+    }
+
+    /// Seed the filter was built with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Bytes of the borrowed fingerprint array.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.b)
+    }
+
+    /// 1 / 2^BITS of the fingerprint type, the chance that a missing key matches.
+    pub fn expected_fpr(&self) -> f64 {
+        1f64 / 2f64.powi(F::BITS as i32)
+    }
+}
+
+impl<T: Hash + ?Sized, F: Fingerprint, S: SerializableHasher> Filter<T> for XorFilterRef<'_, F, S> {
+    fn contains(&self, item: &T) -> bool {
+        XorFilterRef::<F, S>::contains(self, item)
+    }
+
+    fn len(&self) -> usize {
+        XorFilterRef::<F, S>::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        XorFilterRef::<F, S>::len(self)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        XorFilterRef::<F, S>::size_in_bytes(self)
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        XorFilterRef::<F, S>::expected_fpr(self)
+    }
+}
+
+// Keys with the same 64-bit hash map to the same slots and fingerprint, so peeling can never
+// separate them. Each hash value is kept once.
+fn unique_hashes(key_hashes: &[u64]) -> Vec<u64> {
+    let mut hashes = key_hashes.to_vec();
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes
+}
+
 fn compute_mean_and_variance(times: &[Duration]) -> (f64, f64) {
     let times_in_secs: Vec<f64> = times.iter()
         .map(|d| d.as_secs_f64())
@@ -138,6 +441,7 @@ fn compute_mean_and_variance(times: &[Duration]) -> (f64, f64) {
     (mean, variance)
 }
 
+/// Benchmarks the xor filters and prints space, false positive rate and timings.
 pub fn test_xor_filters(){
     //Carry out a single test.
     let pos_keys: Vec<u32> = (1..=996147).collect();
@@ -145,9 +449,9 @@ pub fn test_xor_filters(){
     let pos_key_len = pos_keys.len();
     let neg_key_len = neg_keys.len();
     let xor_construction_start=Instant::now();
-    let filter = XorFilter::new(&pos_keys);
+    let filter = Xor8::new(&pos_keys).expect("xor filter construction failed");
     let xor_construction_duration = xor_construction_start.elapsed();
-    let bits_per_item =(filter.b.len()*8) as f64/pos_key_len as f64;
+    let bits_per_item =(filter.size_in_bytes()*8) as f64/pos_key_len as f64;
     println!("Xor Filter Construction Time per item for {:?} items: {:?}", pos_key_len,xor_construction_duration/pos_key_len as u32);
     println!("Xor bits/item is {:.2}", bits_per_item);
     let mut true_positives = 0;
@@ -185,7 +489,7 @@ pub fn test_xor_filters(){
         let pos_keys: Vec<u32> = (1..=num_of_keys).collect();
         let neg_keys: Vec<u32> = (num_of_keys+1..=2*num_of_keys).collect();
         let xor_construction_start = Instant::now();
-        let filter = XorFilter::new(&pos_keys);
+        let filter = Xor8::new(&pos_keys).expect("xor filter construction failed");
         let xor_construction_duration = xor_construction_start.elapsed();
         construct_times.push(xor_construction_duration);
 
@@ -213,3 +517,74 @@ pub fn test_xor_filters(){
     println!("XOR: Negative Check for {:?} items in total - Mean: {:.6} sec, Variance: {:.6}", num_of_keys, neg_check_mean, neg_check_variance);
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::tests::{assert_rejects_corruption, assert_round_trips, at_offset};
+
+    fn keys() -> Vec<u32> {
+        (0..1000).collect()
+    }
+
+    #[test]
+    fn repeated_keys_are_stored_once() {
+        let filter = Xor8::with_seed(&[5u32; 1000], 1).unwrap();
+        assert_eq!(filter.len(), 1);
+        assert!(filter.contains(&5u32));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts_in_an_undersized_table() {
+        let hashes: Vec<u64> = (0..1000u64).map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15)).collect();
+        let result = Xor8::build(&hashes, 33, 7, DefaultBuildHasher::default());
+        assert!(matches!(result, Err(ConstructionError::TooManyRetries { attempts: MAX_ATTEMPTS })));
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let filter = Xor8::with_seed(&keys(), 7).unwrap();
+        let loaded = assert_round_trips(&filter.to_bytes(), Xor8::from_bytes, |bytes| Xor8::read_from(bytes), |filter, out| filter.write_to(out));
+        assert_eq!(loaded.len(), filter.len());
+        assert!((0..2000u32).all(|i| loaded.contains(&i) == filter.contains(&i)));
+        let filter = Xor16::with_seed(&keys(), 7).unwrap();
+        assert_round_trips(&filter.to_bytes(), Xor16::from_bytes, |bytes| Xor16::read_from(bytes), |filter, out| filter.write_to(out));
+    }
+
+    #[test]
+    fn rejects_another_fingerprint_width() {
+        let bytes = Xor8::with_seed(&keys(), 7).unwrap().to_bytes();
+        assert!(matches!(Xor16::from_bytes(&bytes), Err(SerializationError::InvalidParameters(_))));
+    }
+
+    #[test]
+    fn view_answers_like_the_filter() {
+        let filter = Xor16::with_seed(&keys(), 7).unwrap();
+        at_offset(&filter.to_bytes(), 0, |bytes| {
+            let view = XorFilterRef::<u16>::from_bytes(bytes).unwrap();
+            assert_eq!(view.len(), filter.len());
+            assert!((0..2000u32).all(|i| view.contains(&i) == filter.contains(&i)));
+        });
+    }
+
+    #[test]
+    fn view_rejects_misaligned_or_corrupted_bytes() {
+        let bytes = Xor16::with_seed(&keys(), 7).unwrap().to_bytes();
+        at_offset(&bytes, 1, |bytes| assert!(matches!(XorFilterRef::<u16>::from_bytes(bytes), Err(SerializationError::Misaligned))));
+        at_offset(&bytes, 0, |bytes| assert_rejects_corruption(bytes, |bytes| XorFilterRef::<u16>::from_bytes(bytes).map(|_| ())));
+    }
+
+    #[test]
+    fn unchecked_view_skips_only_the_checksum() {
+        let filter = Xor16::with_seed(&keys(), 7).unwrap();
+        let mut bytes = filter.to_bytes();
+        let checksum = bytes.len() - 4;
+        bytes[checksum] ^= 1;
+        at_offset(&bytes, 0, |bytes| {
+            assert!(matches!(XorFilterRef::<u16>::from_bytes(bytes), Err(SerializationError::ChecksumMismatch { .. })));
+            let view = XorFilterRef::<u16>::from_bytes_unchecked(bytes).unwrap();
+            assert!((0..2000u32).all(|i| view.contains(&i) == filter.contains(&i)));
+        });
+        at_offset(&bytes, 1, |bytes| assert!(matches!(XorFilterRef::<u16>::from_bytes_unchecked(bytes), Err(SerializationError::Misaligned))));
+    }
+}